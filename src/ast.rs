@@ -0,0 +1,180 @@
+use crate::format::DisplayMode;
+use crate::interpreter::Number;
+use crate::parser::Expr;
+use crate::session::Trace;
+use crate::tokenizer::Token;
+
+fn label(expr: &Expr) -> String {
+    match expr {
+        Expr::Calc(op, _, _) => op.to_string(),
+        Expr::Call(func, _) => func.to_string(),
+        Expr::Store(name, _) => format!("={}", name),
+        Expr::Literal(token) => token.to_string(),
+    }
+}
+
+fn children(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Calc(_, left, right) => vec![left, right],
+        Expr::Call(_, args) => args.iter().collect(),
+        Expr::Store(_, value) => vec![value],
+        Expr::Literal(_) => vec![],
+    }
+}
+
+/// A Graphviz graph with a node per operation and literal, operands ordered
+/// left to right.
+pub fn dot(program: &[Expr]) -> String {
+    let mut lines = vec!["digraph ast {".to_string()];
+    let mut next = 0;
+    for expr in program {
+        dot_node(expr, &mut next, &mut lines);
+    }
+    lines.push("}".into());
+    lines.join("\n")
+}
+
+fn dot_node(expr: &Expr, next: &mut usize, lines: &mut Vec<String>) -> usize {
+    let id = *next;
+    *next += 1;
+    let label = label(expr).replace('\\', "\\\\").replace('"', "\\\"");
+    lines.push(format!("  n{} [label=\"{}\"];", id, label));
+    for child in children(expr) {
+        let child = dot_node(child, next, lines);
+        lines.push(format!("  n{} -> n{};", id, child));
+    }
+    id
+}
+
+struct Node {
+    label: String,
+    value: Option<Number>,
+    literal: bool,
+    children: Vec<Node>,
+}
+
+/// Pairs each node with its value. Values were recorded children first, so
+/// the tree is walked in the same order; nodes after a failure get none.
+fn annotate(expr: &Expr, values: &mut impl Iterator<Item = Number>) -> Node {
+    let children = children(expr)
+        .into_iter()
+        .map(|child| annotate(child, values))
+        .collect();
+    // Quotations aren't evaluated on their own.
+    let value = match expr {
+        Expr::Literal(Token::Quote(_)) => None,
+        _ => values.next(),
+    };
+    Node {
+        label: label(expr),
+        value,
+        literal: matches!(
+            expr,
+            Expr::Literal(Token::Int(_) | Token::Float(_) | Token::Date(_) | Token::Duration(_))
+        ),
+        children,
+    }
+}
+
+/// An indented tree of the last line evaluated, with the value worked out at
+/// each node, e.g. `+ = 7`.
+pub fn tree(trace: &Trace, display: &DisplayMode) -> String {
+    let mut values = trace.values.iter().cloned();
+    let mut lines = vec![];
+    for expr in &trace.program {
+        let node = annotate(expr, &mut values);
+        tree_node(&node, display, "", "", &mut lines);
+    }
+    lines.join("\n")
+}
+
+fn tree_node(
+    node: &Node,
+    display: &DisplayMode,
+    lead: &str,
+    indent: &str,
+    lines: &mut Vec<String>,
+) {
+    let mut line = format!("{}{}", lead, node.label);
+    if let (Some(value), false) = (&node.value, node.literal) {
+        line.push_str(&format!(" = {}", display.format(value)));
+    }
+    lines.push(line);
+    for (i, child) in node.children.iter().enumerate() {
+        let (lead, more) = match i + 1 == node.children.len() {
+            true => ("└── ", "    "),
+            false => ("├── ", "│   "),
+        };
+        tree_node(
+            child,
+            display,
+            &format!("{}{}", indent, lead),
+            &format!("{}{}", indent, more),
+            lines,
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::Notation;
+    use crate::session::Session;
+
+    #[test]
+    fn test_dot() {
+        let mut session = Session::default();
+        session.run("3 4 + 2 * =x ; 1".into()).unwrap();
+        assert_eq!(
+            dot(&session.trace.program),
+            "digraph ast {
+  n0 [label=\"=x\"];
+  n1 [label=\"*\"];
+  n2 [label=\"+\"];
+  n3 [label=\"3\"];
+  n2 -> n3;
+  n4 [label=\"4\"];
+  n2 -> n4;
+  n1 -> n2;
+  n5 [label=\"2\"];
+  n1 -> n5;
+  n0 -> n1;
+  n6 [label=\"1\"];
+}"
+        );
+    }
+
+    #[test]
+    fn test_tree() {
+        let mut session = Session::default();
+        session.run("2 =y".into()).unwrap();
+        session.run("3 4 + y 3 ^ * ; 1.5 sqrt neg".into()).unwrap();
+        assert_eq!(
+            tree(&session.trace, &session.display),
+            "* = 56
+├── + = 7
+│   ├── 3
+│   └── 4
+└── ^ = 8
+    ├── y = 2
+    └── 3
+neg = -1.2247449
+└── sqrt = 1.2247449
+    └── 1.5"
+        );
+
+        session.run("y z + 2 *".into()).unwrap_err();
+        let display = DisplayMode {
+            notation: Notation::Fix(1),
+            ..DisplayMode::default()
+        };
+        assert_eq!(
+            tree(&session.trace, &display),
+            "*
+├── +
+│   ├── y = 2
+│   └── z
+└── 2"
+        );
+    }
+}
@@ -0,0 +1,525 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+
+use crate::format::{self, DisplayMode};
+use crate::session::Syntax;
+
+pub const USAGE: &str = "\
+Usage: rpn [OPTIONS] [FILE]
+
+Evaluates reverse polish notation expressions. With no FILE the calculator
+reads from stdin, starting an interactive prompt when stdin is a terminal.
+
+Arguments:
+  [FILE]             Script to run, one line of expressions at a time.
+                     `-` reads from stdin without a prompt.
+
+Options:
+  -e, --expr <EXPR>  Evaluate EXPR and exit, may be given more than once
+  --format <FORMAT>  How to print results: `lines` (one per line), `inline`
+                     (comma separated) or `json` (one object per input line)
+  --infix            Read infix expressions like `(3 + 4) * 2 ^ 3`
+  --show-rpn         Print each line as RPN before its result
+  --rates <FILE>     Exchange rates for `>eur` and the like, a CSV of
+                     `currency,rate,date` rows or a `.toml` file
+  --optimize         Fold constants and simplify identities like `x 0 +`
+                     before evaluating
+  --ast <FORMAT>     Print each line's syntax tree instead of its result:
+                     `dot` (Graphviz), `tree` (annotated with values) or
+                     `json` (needs the `serde` feature)
+  --dump-ast json    Same as `--ast json`
+  --load-ast <FILE>  Evaluate syntax trees written by `--dump-ast json`
+  --fix <N>          Show N digits after the decimal point
+  --sci <N>          Scientific notation with N digits after the point
+  --eng <N>          Engineering notation with N digits after the point
+  --sig <N>          Show N significant digits
+  --thousands        Separate groups of thousands
+  --decimal-mark <C> Use `.` or `,` as the decimal mark
+  --csv              Read FILE or stdin as a table and add a column computed
+                     by the single `--expr`, which can use `$2` or `$name`
+  --tsv              Like `--csv` for tab separated values
+  --no-header        The table's first row is data, not column names
+  --column <COL>     Column to write, replaced if it exists (default `result`)
+  --fail-fast        Stop at the first line that fails
+  --keep-going       Keep evaluating after a failing line (default)
+  -h, --help         Print this help and exit
+  -V, --version      Print the version and exit
+";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OutputFormat {
+    Lines,
+    Inline,
+    Json,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AstFormat {
+    Dot,
+    Tree,
+    Json,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    Auto,
+    Stdin,
+    File(PathBuf),
+    Expressions(Vec<String>),
+    /// Parsed programs from `--dump-ast json`, one line each.
+    Ast(PathBuf),
+}
+
+/// Set by `--csv` or `--tsv` to compute a column of a table.
+#[derive(Debug, PartialEq)]
+pub struct Table {
+    pub expr: String,
+    pub delimiter: char,
+    pub header: bool,
+    /// A column name or number; `None` appends a `result` column.
+    pub column: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub input: Input,
+    pub format: Option<OutputFormat>,
+    pub fail_fast: bool,
+    pub display: DisplayMode,
+    pub table: Option<Table>,
+    pub syntax: Syntax,
+    pub show_rpn: bool,
+    pub ast: Option<AstFormat>,
+    pub optimize: bool,
+    /// A file of exchange rates from `--rates`.
+    pub rates: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run(Options),
+    Help,
+    Version,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CliError {
+    UnknownFlag(String),
+    MissingValue(String),
+    InvalidValue(String, String),
+    ConflictingInputs,
+    TableExpression,
+    NeedsSerde(String),
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::UnknownFlag(flag) => write!(f, "unknown option `{}`", flag),
+            CliError::MissingValue(flag) => write!(f, "option `{}` needs a value", flag),
+            CliError::InvalidValue(flag, value) => {
+                write!(f, "`{}` is not a valid value for `{}`", value, flag)
+            }
+            CliError::ConflictingInputs => {
+                write!(f, "only one of FILE, `-` or `--expr` can be given")
+            }
+            CliError::NeedsSerde(flag) => {
+                write!(f, "`{}` needs rpn built with `--features serde`", flag)
+            }
+            CliError::TableExpression => {
+                write!(f, "`--csv` and `--tsv` need exactly one `--expr`")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, CliError> {
+    let mut options = Options {
+        input: Input::Auto,
+        format: None,
+        fail_fast: false,
+        display: DisplayMode::default(),
+        table: None,
+        syntax: Syntax::Rpn,
+        show_rpn: false,
+        ast: None,
+        optimize: false,
+        rates: None,
+    };
+    let mut exprs = vec![];
+    let mut delimiter = None;
+    let mut header = true;
+    let mut column = None;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-e" | "--expr" => {
+                exprs.push(args.next().ok_or(CliError::MissingValue(arg))?);
+            }
+            "--format" => {
+                let value = args.next().ok_or(CliError::MissingValue(arg.clone()))?;
+                options.format = Some(match value.as_str() {
+                    "lines" => OutputFormat::Lines,
+                    "inline" => OutputFormat::Inline,
+                    "json" => OutputFormat::Json,
+                    _ => return Err(CliError::InvalidValue(arg, value)),
+                });
+            }
+            "--infix" => options.syntax = Syntax::Infix,
+            "--show-rpn" => options.show_rpn = true,
+            "--optimize" => options.optimize = true,
+            "--rates" => options.rates = Some(args.next().ok_or(CliError::MissingValue(arg))?),
+            "--ast" | "--dump-ast" => {
+                let value = args.next().ok_or(CliError::MissingValue(arg.clone()))?;
+                options.ast = Some(match (arg.as_str(), value.as_str()) {
+                    ("--ast", "dot") => AstFormat::Dot,
+                    ("--ast", "tree") => AstFormat::Tree,
+                    (_, "json") if !cfg!(feature = "serde") => {
+                        return Err(CliError::NeedsSerde(arg))
+                    }
+                    (_, "json") => AstFormat::Json,
+                    _ => return Err(CliError::InvalidValue(arg, value)),
+                });
+            }
+            "--load-ast" => {
+                let path = args.next().ok_or(CliError::MissingValue(arg.clone()))?;
+                if !cfg!(feature = "serde") {
+                    return Err(CliError::NeedsSerde(arg));
+                }
+                options.input = set_input(options.input, Input::Ast(path.into()))?;
+            }
+            "--fix" | "--sci" | "--eng" | "--sig" => {
+                let value = args.next().ok_or(CliError::MissingValue(arg.clone()))?;
+                options.display.notation = format::notation(&arg[2..], &value)
+                    .ok_or(CliError::InvalidValue(arg, value))?;
+            }
+            "--thousands" => options.display.thousands = true,
+            "--decimal-mark" => {
+                let value = args.next().ok_or(CliError::MissingValue(arg.clone()))?;
+                options.display.decimal_mark = match value.as_str() {
+                    "." => '.',
+                    "," => ',',
+                    _ => return Err(CliError::InvalidValue(arg, value)),
+                };
+            }
+            "--csv" => delimiter = Some(','),
+            "--tsv" => delimiter = Some('\t'),
+            "--no-header" => header = false,
+            "--column" => column = Some(args.next().ok_or(CliError::MissingValue(arg))?),
+            "--fail-fast" => options.fail_fast = true,
+            "--keep-going" => options.fail_fast = false,
+            "-" => options.input = set_input(options.input, Input::Stdin)?,
+            flag if flag.starts_with('-') => return Err(CliError::UnknownFlag(arg)),
+            _ => options.input = set_input(options.input, Input::File(arg.into()))?,
+        }
+    }
+
+    match delimiter {
+        // The table is read from FILE or stdin, so the expression doesn't
+        // count as an input.
+        Some(delimiter) if exprs.len() == 1 => {
+            options.table = Some(Table {
+                expr: exprs.remove(0),
+                delimiter,
+                header,
+                column,
+            })
+        }
+        Some(_) => return Err(CliError::TableExpression),
+        None if exprs.is_empty() => {}
+        None => options.input = set_input(options.input, Input::Expressions(exprs))?,
+    }
+    Ok(Command::Run(options))
+}
+
+fn set_input(current: Input, new: Input) -> Result<Input, CliError> {
+    match current {
+        Input::Auto => Ok(new),
+        _ => Err(CliError::ConflictingInputs),
+    }
+}
+
+/// Whether the script starts with a `#!` line that should be skipped, so
+/// scripts can be made executable.
+pub fn has_shebang(script: &str) -> bool {
+    script.starts_with("#!")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::Notation;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            parse_args(args(&[])),
+            Ok(Command::Run(Options {
+                input: Input::Auto,
+                format: None,
+                fail_fast: false,
+                display: DisplayMode::default(),
+                table: None,
+                syntax: Syntax::Rpn,
+                show_rpn: false,
+                ast: None,
+                optimize: false,
+                rates: None
+            }))
+        );
+        assert_eq!(parse_args(args(&["--help"])), Ok(Command::Help));
+        assert_eq!(parse_args(args(&["-e", "1", "-V"])), Ok(Command::Version));
+        assert_eq!(
+            parse_args(args(&[
+                "-e", "3 4 +", "--expr", "1 1 -", "--format", "inline"
+            ])),
+            Ok(Command::Run(Options {
+                input: Input::Expressions(vec!["3 4 +".into(), "1 1 -".into()]),
+                format: Some(OutputFormat::Inline),
+                fail_fast: false,
+                display: DisplayMode::default(),
+                table: None,
+                syntax: Syntax::Rpn,
+                show_rpn: false,
+                ast: None,
+                optimize: false,
+                rates: None
+            }))
+        );
+        assert_eq!(
+            parse_args(args(&["script.rpn"])),
+            Ok(Command::Run(Options {
+                input: Input::File("script.rpn".into()),
+                format: None,
+                fail_fast: false,
+                display: DisplayMode::default(),
+                table: None,
+                syntax: Syntax::Rpn,
+                show_rpn: false,
+                ast: None,
+                optimize: false,
+                rates: None
+            }))
+        );
+        assert_eq!(
+            parse_args(args(&["--format", "json", "-"])),
+            Ok(Command::Run(Options {
+                input: Input::Stdin,
+                format: Some(OutputFormat::Json),
+                fail_fast: false,
+                display: DisplayMode::default(),
+                table: None,
+                syntax: Syntax::Rpn,
+                show_rpn: false,
+                ast: None,
+                optimize: false,
+                rates: None
+            }))
+        );
+        assert_eq!(
+            parse_args(args(&[
+                "--infix",
+                "--show-rpn",
+                "--optimize",
+                "--rates",
+                "rates.csv",
+                "-e",
+                "(1 + 2) * 3"
+            ])),
+            Ok(Command::Run(Options {
+                input: Input::Expressions(vec!["(1 + 2) * 3".into()]),
+                format: None,
+                fail_fast: false,
+                display: DisplayMode::default(),
+                table: None,
+                syntax: Syntax::Infix,
+                show_rpn: true,
+                ast: None,
+                optimize: true,
+                rates: Some("rates.csv".into())
+            }))
+        );
+        assert_eq!(
+            parse_args(args(&["-"])),
+            Ok(Command::Run(Options {
+                input: Input::Stdin,
+                format: None,
+                fail_fast: false,
+                display: DisplayMode::default(),
+                table: None,
+                syntax: Syntax::Rpn,
+                show_rpn: false,
+                ast: None,
+                optimize: false,
+                rates: None
+            }))
+        );
+
+        assert_eq!(
+            parse_args(args(&["--keep-going", "--fail-fast", "-"])),
+            Ok(Command::Run(Options {
+                input: Input::Stdin,
+                format: None,
+                fail_fast: true,
+                display: DisplayMode::default(),
+                table: None,
+                syntax: Syntax::Rpn,
+                show_rpn: false,
+                ast: None,
+                optimize: false,
+                rates: None
+            }))
+        );
+        assert_eq!(
+            parse_args(args(&["--fail-fast", "--keep-going"])),
+            Ok(Command::Run(Options {
+                input: Input::Auto,
+                format: None,
+                fail_fast: false,
+                display: DisplayMode::default(),
+                table: None,
+                syntax: Syntax::Rpn,
+                show_rpn: false,
+                ast: None,
+                optimize: false,
+                rates: None
+            }))
+        );
+
+        assert_eq!(
+            parse_args(args(&["--sci", "3", "--thousands", "--decimal-mark", ","])),
+            Ok(Command::Run(Options {
+                input: Input::Auto,
+                format: None,
+                fail_fast: false,
+                display: DisplayMode {
+                    notation: Notation::Sci(3),
+                    thousands: true,
+                    decimal_mark: ','
+                },
+                table: None,
+                syntax: Syntax::Rpn,
+                show_rpn: false,
+                ast: None,
+                optimize: false,
+                rates: None
+            }))
+        );
+        assert_eq!(
+            parse_args(args(&["--fix", "-1"])),
+            Err(CliError::InvalidValue("--fix".into(), "-1".into()))
+        );
+        assert_eq!(
+            parse_args(args(&["--decimal-mark", "_"])),
+            Err(CliError::InvalidValue("--decimal-mark".into(), "_".into()))
+        );
+
+        assert_eq!(
+            parse_args(args(&["-e"])),
+            Err(CliError::MissingValue("-e".into()))
+        );
+        assert_eq!(
+            parse_args(args(&[
+                "--tsv", "-e", "$2 2 *", "data.tsv", "--column", "2"
+            ])),
+            Ok(Command::Run(Options {
+                input: Input::File("data.tsv".into()),
+                format: None,
+                fail_fast: false,
+                display: DisplayMode::default(),
+                table: Some(Table {
+                    expr: "$2 2 *".into(),
+                    delimiter: '\t',
+                    header: true,
+                    column: Some("2".into())
+                }),
+                syntax: Syntax::Rpn,
+                show_rpn: false,
+                ast: None,
+                optimize: false,
+                rates: None
+            }))
+        );
+        assert_eq!(
+            parse_args(args(&["--csv", "--no-header", "-e", "$1", "-e", "$2"])),
+            Err(CliError::TableExpression)
+        );
+        assert_eq!(
+            parse_args(args(&["--ast", "tree"])),
+            Ok(Command::Run(Options {
+                input: Input::Auto,
+                format: None,
+                fail_fast: false,
+                display: DisplayMode::default(),
+                table: None,
+                syntax: Syntax::Rpn,
+                show_rpn: false,
+                ast: Some(AstFormat::Tree),
+                optimize: false,
+                rates: None
+            }))
+        );
+        assert_eq!(
+            parse_args(args(&["--ast", "png"])),
+            Err(CliError::InvalidValue("--ast".into(), "png".into()))
+        );
+        assert_eq!(
+            parse_args(args(&["--dump-ast", "tree"])),
+            Err(CliError::InvalidValue("--dump-ast".into(), "tree".into()))
+        );
+        if cfg!(feature = "serde") {
+            assert_eq!(
+                parse_args(args(&["--load-ast", "ast.json"])),
+                Ok(Command::Run(Options {
+                    input: Input::Ast("ast.json".into()),
+                    format: None,
+                    fail_fast: false,
+                    display: DisplayMode::default(),
+                    table: None,
+                    syntax: Syntax::Rpn,
+                    show_rpn: false,
+                    ast: None,
+                    optimize: false,
+                    rates: None
+                }))
+            );
+        } else {
+            assert_eq!(
+                parse_args(args(&["--dump-ast", "json"])),
+                Err(CliError::NeedsSerde("--dump-ast".into()))
+            );
+        }
+        assert_eq!(
+            parse_args(args(&["--format", "xml"])),
+            Err(CliError::InvalidValue("--format".into(), "xml".into()))
+        );
+        assert_eq!(
+            parse_args(args(&["--verbose"])),
+            Err(CliError::UnknownFlag("--verbose".into()))
+        );
+        assert_eq!(
+            parse_args(args(&["script.rpn", "-"])),
+            Err(CliError::ConflictingInputs)
+        );
+        assert_eq!(
+            parse_args(args(&["-e", "1", "script.rpn"])),
+            Err(CliError::ConflictingInputs)
+        );
+    }
+
+    #[test]
+    fn test_has_shebang() {
+        assert!(has_shebang("#!/usr/bin/env rpn\n1 2 +\n"));
+        assert!(!has_shebang("1 2 +\n"));
+        assert!(!has_shebang(" #!\n"));
+    }
+}
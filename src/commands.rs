@@ -0,0 +1,590 @@
+use std::fmt::Display;
+
+use crate::dates;
+use crate::format::{self, Notation};
+use crate::interpreter::Number;
+use crate::money::{self, Rates};
+use crate::parser::Expr;
+use crate::printer::{self, Parens};
+use crate::report;
+use crate::session::{Session, Syntax};
+use crate::symbolic;
+use crate::tokenizer::{self, Token, BUILTINS};
+
+/// REPL commands, written with a leading `:` so they never reach the
+/// tokenizer.
+#[derive(Debug, PartialEq)]
+pub enum MetaCommand {
+    Help,
+    Stack,
+    Vars,
+    Clear,
+    Reset,
+    Undo,
+    Redo,
+    Display(Notation),
+    Thousands(bool),
+    DecimalMark(char),
+    Syntax(Syntax),
+    Evaluate(Syntax, String),
+    Explain(String),
+    /// `:d/dx`, the variable and the expression to differentiate.
+    Derivative(String, String),
+    Rates,
+    /// `:rates load FILE`.
+    LoadRates(String),
+    Stats,
+    ClearStats,
+    Quit,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CommandError {
+    Unknown(String),
+    InvalidArgument(String, String),
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandError::Unknown(name) => {
+                write!(f, "unknown command `:{}`, try `:help`", name)
+            }
+            CommandError::InvalidArgument(name, arg) => {
+                write!(f, "`{}` is not a valid argument for `:{}`", arg, name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+pub enum Outcome {
+    Output(String),
+    /// A line to evaluate as usual but written in the given syntax.
+    Evaluate(Syntax, String),
+    Quit,
+}
+
+pub const COMMANDS: &[(&str, &str)] = &[
+    (":help", "show this help"),
+    (":stack", "show every result so far, the most recent last"),
+    (":vars", "show defined variables and words"),
+    (":clear", "empty the stack"),
+    (
+        ":reset",
+        "forget the stack, variables, words and statistics",
+    ),
+    (":undo", "go back to before the previous line"),
+    (":redo", "reapply a line that was undone"),
+    (":fix", "show N digits after the decimal point"),
+    (":sci", "scientific notation with N digits after the point"),
+    (":eng", "engineering notation with N digits after the point"),
+    (":sig", "show N significant digits"),
+    (":std", "show numbers as they are stored"),
+    (":thousands", "on or off, separate groups of thousands"),
+    (":decimal-mark", "use . or , as the decimal mark"),
+    (
+        ":infix",
+        "read infix from now on, or evaluate the rest of the line",
+    ),
+    (
+        ":rpn",
+        "read RPN from now on, or evaluate the rest of the line",
+    ),
+    (
+        ":explain",
+        "show the rest of the line as infix, RPN and an S-expression",
+    ),
+    (
+        ":d/dx",
+        "differentiate the rest of the line with respect to x, or another variable",
+    ),
+    (
+        ":rates",
+        "show the exchange rates, or `:rates load FILE` to read a CSV or TOML table",
+    ),
+    (
+        ":stats",
+        "show the statistics register, or `:stats clear` to empty it",
+    ),
+    (":quit", "leave the calculator, as does Ctrl-D"),
+];
+
+/// Returns `None` when the line is not a command and should be evaluated.
+pub fn parse(line: &str) -> Option<Result<MetaCommand, CommandError>> {
+    let line = line.trim().strip_prefix(':')?;
+    let (name, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let arg = arg.trim();
+    let invalid = || CommandError::InvalidArgument(name.into(), arg.into());
+    Some(match name {
+        "help" | "h" | "?" => Ok(MetaCommand::Help),
+        "stack" | "s" => Ok(MetaCommand::Stack),
+        "vars" | "v" => Ok(MetaCommand::Vars),
+        "clear" | "c" => Ok(MetaCommand::Clear),
+        "reset" => Ok(MetaCommand::Reset),
+        "undo" | "u" => Ok(MetaCommand::Undo),
+        "redo" | "r" => Ok(MetaCommand::Redo),
+        "std" => Ok(MetaCommand::Display(Notation::Std)),
+        "fix" | "sci" | "eng" | "sig" => format::notation(name, arg)
+            .map(MetaCommand::Display)
+            .ok_or_else(invalid),
+        "thousands" => match arg {
+            "on" => Ok(MetaCommand::Thousands(true)),
+            "off" => Ok(MetaCommand::Thousands(false)),
+            _ => Err(invalid()),
+        },
+        "decimal-mark" => match arg {
+            "." => Ok(MetaCommand::DecimalMark('.')),
+            "," => Ok(MetaCommand::DecimalMark(',')),
+            _ => Err(invalid()),
+        },
+        "infix" | "rpn" => {
+            let syntax = match name {
+                "infix" => Syntax::Infix,
+                _ => Syntax::Rpn,
+            };
+            match arg {
+                "" => Ok(MetaCommand::Syntax(syntax)),
+                text => Ok(MetaCommand::Evaluate(syntax, text.into())),
+            }
+        }
+        "explain" if !arg.is_empty() => Ok(MetaCommand::Explain(arg.into())),
+        "explain" => Err(invalid()),
+        name if name.starts_with("d/d") => {
+            let var = &name[3..];
+            match tokenizer::is_identifier(var) && !arg.is_empty() {
+                true => Ok(MetaCommand::Derivative(var.into(), arg.into())),
+                false => Err(invalid()),
+            }
+        }
+        "rates" if arg.is_empty() => Ok(MetaCommand::Rates),
+        "rates" => match arg.split_once(char::is_whitespace) {
+            Some(("load", path)) => Ok(MetaCommand::LoadRates(path.trim().into())),
+            _ => Err(invalid()),
+        },
+        "stats" => match arg {
+            "" => Ok(MetaCommand::Stats),
+            "clear" => Ok(MetaCommand::ClearStats),
+            _ => Err(invalid()),
+        },
+        "quit" | "q" | "exit" => Ok(MetaCommand::Quit),
+        name => Err(CommandError::Unknown(name.into())),
+    })
+}
+
+impl MetaCommand {
+    pub fn run(&self, session: &mut Session) -> Outcome {
+        match self {
+            MetaCommand::Help => Outcome::Output(help()),
+            MetaCommand::Stack => Outcome::Output(stack(session)),
+            MetaCommand::Vars => Outcome::Output(vars(session)),
+            MetaCommand::Clear => {
+                session.checkpoint();
+                session.stack.clear();
+                Outcome::Output("stack cleared".into())
+            }
+            MetaCommand::Reset => {
+                session.reset();
+                Outcome::Output("session reset".into())
+            }
+            MetaCommand::Undo => Outcome::Output(match session.undo() {
+                true => stack(session),
+                false => "nothing to undo".into(),
+            }),
+            MetaCommand::Redo => Outcome::Output(match session.redo() {
+                true => stack(session),
+                false => "nothing to redo".into(),
+            }),
+            MetaCommand::Display(notation) => {
+                session.display.notation = *notation;
+                Outcome::Output(stack(session))
+            }
+            MetaCommand::Thousands(thousands) => {
+                session.display.thousands = *thousands;
+                Outcome::Output(stack(session))
+            }
+            MetaCommand::DecimalMark(mark) => {
+                session.display.decimal_mark = *mark;
+                Outcome::Output(stack(session))
+            }
+            MetaCommand::Syntax(syntax) => {
+                session.syntax = *syntax;
+                Outcome::Output(match syntax {
+                    Syntax::Rpn => "reading RPN".into(),
+                    Syntax::Infix => "reading infix".into(),
+                })
+            }
+            MetaCommand::Evaluate(syntax, text) => Outcome::Evaluate(*syntax, text.clone()),
+            MetaCommand::Explain(text) => Outcome::Output(explain(session, text)),
+            MetaCommand::Derivative(var, text) => Outcome::Output(derivative(session, var, text)),
+            MetaCommand::Rates => Outcome::Output(rates(session)),
+            MetaCommand::LoadRates(path) => {
+                Outcome::Output(match Rates::load(path, dates::today()) {
+                    Ok(rates) => {
+                        session.env.rates = rates;
+                        self::rates(session)
+                    }
+                    Err(e) => e.to_string(),
+                })
+            }
+            MetaCommand::Stats => Outcome::Output(stats(session)),
+            MetaCommand::ClearStats => {
+                session.checkpoint();
+                session.env.stats.samples.clear();
+                Outcome::Output("statistics cleared".into())
+            }
+            MetaCommand::Quit => Outcome::Quit,
+        }
+    }
+}
+
+fn help() -> String {
+    let mut lines = vec![format!(
+        "{:<6} {:<5} {:<10} {}",
+        "word", "arity", "example", "description"
+    )];
+    for builtin in BUILTINS {
+        lines.push(format!(
+            "{:<6} {:<5} {:<10} {}",
+            builtin.name,
+            builtin.kind.arity(),
+            builtin.example,
+            builtin.description
+        ));
+    }
+    lines.push(String::new());
+    lines.push(format!(
+        "{:<6} {:<16} {}",
+        "=name", "3 4 + =x", "store a variable"
+    ));
+    lines.push(format!(
+        "{:<6} {:<16} {}",
+        "[ ]", "[ 2 * ] =double", "define a word"
+    ));
+    lines.push(format!(
+        "{:<6} {:<16} {}",
+        "90m", "2024-04-07 90m +", "a duration, unlike `90 m` which is 90 metres"
+    ));
+    lines.push(String::new());
+    for (command, description) in COMMANDS {
+        lines.push(format!("{:<14} {}", command, description));
+    }
+    lines.join("\n")
+}
+
+fn explain(session: &Session, text: &str) -> String {
+    let program = match session.parse(text.into()) {
+        Ok(program) => program,
+        Err(e) => return report(&e.into()),
+    };
+    let join = |print: fn(&Expr) -> String, separator| {
+        program
+            .iter()
+            .map(print)
+            .collect::<Vec<_>>()
+            .join(separator)
+    };
+    [
+        (
+            "infix",
+            join(|expr| printer::infix(expr, Parens::Minimal), "; "),
+        ),
+        (
+            "parens",
+            join(|expr| printer::infix(expr, Parens::Full), "; "),
+        ),
+        ("rpn", join(printer::rpn, " ; ")),
+        ("lisp", join(printer::sexpr, " ")),
+    ]
+    .iter()
+    .map(|(name, text)| format!("{:<6} {}", name, text))
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+/// The derivative of each expression on the line, as infix and RPN.
+fn derivative(session: &Session, var: &str, text: &str) -> String {
+    let program = match session.parse(text.into()) {
+        Ok(program) => program,
+        Err(e) => return report(&e.into()),
+    };
+    let derivatives: Vec<Expr> = program
+        .iter()
+        .map(|expr| symbolic::derivative(expr, var))
+        .collect();
+    let infix: Vec<String> = derivatives
+        .iter()
+        .map(|expr| printer::infix(expr, Parens::Minimal))
+        .collect();
+    let rpn: Vec<String> = derivatives.iter().map(printer::rpn).collect();
+    format!(
+        "{:<6} {}\n{:<6} {}",
+        "infix",
+        infix.join("; "),
+        "rpn",
+        rpn.join(" ; ")
+    )
+}
+
+fn rates(session: &Session) -> String {
+    let rates = &session.env.rates;
+    if rates.rates.is_empty() {
+        return "no exchange rates loaded".into();
+    }
+    rates
+        .rates
+        .iter()
+        .map(|(currency, rate)| {
+            let stale = match rates.today - rate.date > money::MAX_AGE {
+                true => " (stale)",
+                false => "",
+            };
+            format!(
+                "{} {} {}{}",
+                currency,
+                rate.per_base,
+                dates::iso_date(rate.date),
+                stale
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The samples in the order they were added, with `y` after `x` for pairs.
+fn stats(session: &Session) -> String {
+    let samples = &session.env.stats.samples;
+    if samples.is_empty() {
+        return "the statistics register is empty".into();
+    }
+    let format = |n: f64| session.display.format(&Number::Float(n as f32));
+    samples
+        .iter()
+        .enumerate()
+        .map(|(i, sample)| match sample.y {
+            Some(y) => format!("{}: {} {}", i + 1, format(sample.x), format(y)),
+            None => format!("{}: {}", i + 1, format(sample.x)),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn stack(session: &Session) -> String {
+    if session.stack.is_empty() {
+        return "stack is empty".into();
+    }
+    let depth = session.stack.len();
+    session
+        .stack
+        .iter()
+        .enumerate()
+        .map(|(i, val)| format!("{}: {}", depth - i, session.display.format(val)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn vars(session: &Session) -> String {
+    if session.env.vars.is_empty() && session.words.is_empty() {
+        return "nothing is defined".into();
+    }
+    let vars = session
+        .env
+        .vars
+        .iter()
+        .map(|(name, val)| format!("{} = {}", name, session.display.format(val)));
+    let words = session
+        .words
+        .iter()
+        .map(|(name, body)| format!("{} = {}", name, Token::Quote(body.clone())));
+    vars.chain(words).collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn output(command: MetaCommand, session: &mut Session) -> String {
+        match command.run(session) {
+            Outcome::Output(text) => text,
+            Outcome::Evaluate(_, _) | Outcome::Quit => panic!("unexpected outcome"),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("1 2 +"), None);
+        assert_eq!(parse(" :help "), Some(Ok(MetaCommand::Help)));
+        assert_eq!(parse(":q"), Some(Ok(MetaCommand::Quit)));
+        assert_eq!(
+            parse(":fix 4"),
+            Some(Ok(MetaCommand::Display(Notation::Fix(4))))
+        );
+        assert_eq!(
+            parse(":fix"),
+            Some(Err(CommandError::InvalidArgument("fix".into(), "".into())))
+        );
+        assert_eq!(
+            parse(":thousands on"),
+            Some(Ok(MetaCommand::Thousands(true)))
+        );
+        assert_eq!(
+            parse(":decimal-mark ,"),
+            Some(Ok(MetaCommand::DecimalMark(',')))
+        );
+        assert_eq!(
+            parse(":infix (1 + 2) * 3"),
+            Some(Ok(MetaCommand::Evaluate(
+                Syntax::Infix,
+                "(1 + 2) * 3".into()
+            )))
+        );
+        assert_eq!(parse(":rpn"), Some(Ok(MetaCommand::Syntax(Syntax::Rpn))));
+        assert_eq!(
+            parse(":explain 1 2 +"),
+            Some(Ok(MetaCommand::Explain("1 2 +".into())))
+        );
+        assert_eq!(
+            parse(":d/dt t 2 ^"),
+            Some(Ok(MetaCommand::Derivative("t".into(), "t 2 ^".into())))
+        );
+        assert_eq!(
+            parse(":d/dpi 2 pi *"),
+            Some(Err(CommandError::InvalidArgument(
+                "d/dpi".into(),
+                "2 pi *".into()
+            )))
+        );
+        assert_eq!(parse(":rates"), Some(Ok(MetaCommand::Rates)));
+        assert_eq!(
+            parse(":rates load  rates.csv "),
+            Some(Ok(MetaCommand::LoadRates("rates.csv".into())))
+        );
+        assert_eq!(
+            parse(":rates save rates.csv"),
+            Some(Err(CommandError::InvalidArgument(
+                "rates".into(),
+                "save rates.csv".into()
+            )))
+        );
+        assert_eq!(parse(":stats"), Some(Ok(MetaCommand::Stats)));
+        assert_eq!(parse(":stats clear"), Some(Ok(MetaCommand::ClearStats)));
+        assert_eq!(
+            parse(":stats all"),
+            Some(Err(CommandError::InvalidArgument(
+                "stats".into(),
+                "all".into()
+            )))
+        );
+        assert_eq!(
+            parse(":frobnicate"),
+            Some(Err(CommandError::Unknown("frobnicate".into())))
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let mut session = Session::default();
+        assert_eq!(output(MetaCommand::Stack, &mut session), "stack is empty");
+        assert_eq!(
+            output(MetaCommand::Vars, &mut session),
+            "nothing is defined"
+        );
+
+        session.run("3 4 + =x ; 2".into()).unwrap();
+        session.run("[ 2 * ] =double".into()).unwrap();
+        assert_eq!(output(MetaCommand::Stack, &mut session), "2: 7\n1: 2");
+        assert_eq!(
+            output(MetaCommand::Vars, &mut session),
+            "x = 7\ndouble = [ 2 * ]"
+        );
+
+        output(MetaCommand::Clear, &mut session);
+        assert!(session.stack.is_empty());
+        assert!(session.env.vars.contains_key("x"));
+
+        assert_eq!(output(MetaCommand::Undo, &mut session), "2: 7\n1: 2");
+        assert_eq!(output(MetaCommand::Redo, &mut session), "stack is empty");
+        assert_eq!(output(MetaCommand::Redo, &mut session), "nothing to redo");
+
+        output(MetaCommand::Reset, &mut session);
+        assert!(session.env.vars.is_empty());
+        assert!(session.words.is_empty());
+
+        session.run("1234.5".into()).unwrap();
+        output(MetaCommand::Thousands(true), &mut session);
+        output(MetaCommand::DecimalMark(','), &mut session);
+        assert_eq!(
+            output(MetaCommand::Display(Notation::Fix(2)), &mut session),
+            "1: 1.234,50"
+        );
+        assert_eq!(session.stack, vec![Number::Float(1234.5)]);
+
+        assert_eq!(
+            output(MetaCommand::Syntax(Syntax::Infix), &mut session),
+            "reading infix"
+        );
+        assert_eq!(session.syntax, Syntax::Infix);
+        assert!(matches!(
+            MetaCommand::Evaluate(Syntax::Rpn, "1 2 +".into()).run(&mut session),
+            Outcome::Evaluate(Syntax::Rpn, text) if text == "1 2 +"
+        ));
+
+        assert_eq!(
+            output(MetaCommand::Explain("3 - (1 + 2) ; x".into()), &mut session),
+            "infix  3 - (1 + 2); x\n\
+             parens (3 - (1 + 2)); x\n\
+             rpn    3 1 2 + - ; x\n\
+             lisp   (- 3 (+ 1 2)) x"
+        );
+        assert_eq!(
+            output(MetaCommand::Explain("(1".into()), &mut session),
+            "error[E0022]: could not parse input: invalid infix expression: \
+             parentheses are not balanced"
+        );
+        assert!(session.words.is_empty());
+        assert_eq!(
+            output(
+                MetaCommand::Derivative("x".into(), "x ^ 2 + sin(3 * x)".into()),
+                &mut session
+            ),
+            "infix  2 * x + 3 * cos(3 * x)\n\
+             rpn    2 x * 3 3 x * cos * +"
+        );
+
+        assert_eq!(
+            output(MetaCommand::Rates, &mut session),
+            "no exchange rates loaded"
+        );
+        assert!(
+            output(MetaCommand::LoadRates("missing.csv".into()), &mut session)
+                .starts_with("could not read rates: missing.csv: ")
+        );
+        let today = dates::parse_date("2024-04-07").unwrap();
+        session.env.rates =
+            Rates::from_csv("USD,1.0832,2024-04-05\nGBP,0.8571,2024-03-01", today).unwrap();
+        assert_eq!(
+            output(MetaCommand::Rates, &mut session),
+            "USD 1.0832 2024-04-05\nGBP 0.8571 2024-03-01 (stale)"
+        );
+
+        assert_eq!(
+            output(MetaCommand::Stats, &mut session),
+            "the statistics register is empty"
+        );
+        session
+            .run_as(Syntax::Rpn, "2 s+ ; 1 3.5 xy+".into())
+            .unwrap();
+        assert_eq!(
+            output(MetaCommand::Stats, &mut session),
+            "1: 2,00\n2: 1,00 3,50"
+        );
+        output(MetaCommand::ClearStats, &mut session);
+        assert!(session.env.stats.samples.is_empty());
+        session.undo();
+        assert_eq!(session.env.stats.samples.len(), 2);
+
+        assert!(output(MetaCommand::Help, &mut session).contains("+      2     3 4 +"));
+        assert!(matches!(MetaCommand::Quit.run(&mut session), Outcome::Quit));
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+use std::io;
+
+use crate::cli::{Options, Table};
+use crate::interpreter::{self, Env, InterpretError};
+use crate::optimizer;
+use crate::parser::{self, Expr};
+use crate::session::Syntax;
+use crate::{load_rates, report};
+
+const DEFAULT_COLUMN: &str = "result";
+
+/// Splits a line into fields. Fields can be quoted with `"` to contain the
+/// delimiter, and `""` inside quotes is a literal quote.
+pub fn split(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ch if ch == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            ch => field.push(ch),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// The inverse of `split`, quoting only the fields that need it.
+pub fn join(fields: &[String], delimiter: char) -> String {
+    fields
+        .iter()
+        .map(|field| {
+            if field.contains([delimiter, '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(&delimiter.to_string())
+}
+
+fn compile(syntax: Syntax, expr: &str, optimize: bool) -> Result<Vec<Expr>, InterpretError> {
+    let tokens = parser::expand(syntax.translate(expr.into())?, &mut BTreeMap::new())?;
+    let program = parser::parse(tokens)?;
+    Ok(match optimize {
+        true => optimizer::optimize(program, &BTreeMap::new()),
+        false => program,
+    })
+}
+
+/// Where the computed value goes: over an existing column, or on the end.
+fn target(column: Option<&str>, names: &[String]) -> Option<usize> {
+    let column = column?;
+    names.iter().position(|name| name == column).or_else(|| {
+        column
+            .parse::<usize>()
+            .ok()
+            .filter(|n| *n > 0)
+            .map(|n| n - 1)
+    })
+}
+
+fn write_cell(fields: &mut Vec<String>, target: Option<usize>, value: String) {
+    match target {
+        Some(index) => {
+            if fields.len() <= index {
+                fields.resize(index + 1, String::new());
+            }
+            fields[index] = value;
+        }
+        None => fields.push(value),
+    }
+}
+
+/// Evaluates `table.expr` once per row, printing each row with the result
+/// added. Failing rows are reported on stderr and get an empty cell.
+/// Returns whether every row succeeded.
+pub fn run<I: Iterator<Item = (usize, io::Result<String>)>>(
+    mut lines: I,
+    table: &Table,
+    options: &Options,
+) -> bool {
+    let expr = match compile(options.syntax, &table.expr, options.optimize).as_deref() {
+        Ok([expr]) => expr.clone(),
+        Ok(program) => {
+            eprintln!(
+                "rpn: `--expr` must be a single expression, found {}",
+                program.len()
+            );
+            return false;
+        }
+        Err(e) => {
+            eprintln!("rpn: {}", report(e));
+            return false;
+        }
+    };
+
+    let mut env = Env {
+        rates: load_rates(options),
+        ..Env::default()
+    };
+    let column = table.column.as_deref();
+    let mut output = target(column, &[]);
+    if table.header {
+        match lines.next() {
+            Some((_, Ok(line))) => {
+                env.row.names = split(&line, table.delimiter);
+                output = target(column, &env.row.names);
+                let mut header = env.row.names.clone();
+                write_cell(&mut header, output, column.unwrap_or(DEFAULT_COLUMN).into());
+                println!("{}", join(&header, table.delimiter));
+            }
+            Some((_, Err(e))) => {
+                eprintln!("line 1: could not read line: {}", e);
+                return false;
+            }
+            None => return true,
+        }
+    }
+
+    let mut success = true;
+    for (index, line) in lines {
+        let line = match line {
+            Ok(line) if line.is_empty() => continue,
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("line {}: could not read line: {}", index + 1, e);
+                success = false;
+                if options.fail_fast {
+                    break;
+                }
+                continue;
+            }
+        };
+        env.row.values = split(&line, table.delimiter);
+        let cell = match interpreter::eval(&expr, &mut env) {
+            Ok(val) => options.display.format(&val),
+            Err(e) => {
+                eprintln!("line {}: {}", index + 1, report(&e.into()));
+                success = false;
+                if options.fail_fast {
+                    break;
+                }
+                String::new()
+            }
+        };
+        let mut fields = std::mem::take(&mut env.row.values);
+        write_cell(&mut fields, output, cell);
+        println!("{}", join(&fields, table.delimiter));
+    }
+    success
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        assert_eq!(split("a,b,,c", ','), vec!["a", "b", "", "c"]);
+        assert_eq!(
+            split(r#""1,5","say ""hi""",x"y"#, ','),
+            vec!["1,5", "say \"hi\"", "x\"y"]
+        );
+        assert_eq!(split("a,b", '\t'), vec!["a,b"]);
+        assert_eq!(split("", ','), vec![""]);
+
+        let fields = vec!["1,5".to_string(), "a\"b".into(), "plain".into()];
+        assert_eq!(join(&fields, ','), r#""1,5","a""b",plain"#);
+        assert_eq!(split(&join(&fields, ','), ','), fields);
+        assert_eq!(join(&fields, '\t'), "1,5\t\"a\"\"b\"\tplain");
+    }
+
+    #[test]
+    fn test_target() {
+        let names = vec!["qty".to_string(), "price".into()];
+        assert_eq!(target(None, &names), None);
+        assert_eq!(target(Some("price"), &names), Some(1));
+        assert_eq!(target(Some("1"), &names), Some(0));
+        assert_eq!(target(Some("0"), &names), None);
+        assert_eq!(target(Some("total"), &names), None);
+
+        let mut fields = vec!["1".to_string()];
+        write_cell(&mut fields, Some(2), "x".into());
+        assert_eq!(fields, vec!["1", "", "x"]);
+        write_cell(&mut fields, None, "y".into());
+        assert_eq!(fields, vec!["1", "", "x", "y"]);
+    }
+}
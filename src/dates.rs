@@ -0,0 +1,384 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::interpreter::{EvalError, Number};
+use crate::tokenizer::Op;
+
+pub const SECONDS_PER_DAY: i64 = 86400;
+
+/// Suffixes of a duration like `1d12h`, largest first.
+const COMPACT: &[(char, i64)] = &[
+    ('w', 7 * SECONDS_PER_DAY),
+    ('d', SECONDS_PER_DAY),
+    ('h', 3600),
+    ('m', 60),
+    ('s', 1),
+];
+/// The parts of an ISO 8601 duration, `P1W2D` before the `T` and `T3H4M5S`
+/// after. Years and months are left out, their length varies.
+const ISO_DAYS: &[(char, i64)] = &[('W', 7 * SECONDS_PER_DAY), ('D', SECONDS_PER_DAY)];
+const ISO_TIME: &[(char, i64)] = &[('H', 3600), ('M', 60), ('S', 1)];
+
+/// Days since 1970-01-01 in the proleptic Gregorian calendar, after Howard
+/// Hinnant's `days_from_civil`.
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The inverse of `days_from_civil`, the year, month and day.
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The year, month and day of text written like `2024-04-07`, which may
+/// still not be a real date.
+fn date_parts(text: &str) -> Option<(&str, &str, &str)> {
+    let mut parts = text.splitn(3, '-');
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+    let digits =
+        |part: &str, len: usize| part.len() == len && part.bytes().all(|b| b.is_ascii_digit());
+    match digits(year, 4) && digits(month, 2) && digits(day, 2) {
+        true => Some((year, month, day)),
+        false => None,
+    }
+}
+
+/// Whether a word is written like a date, with or without a time, so it
+/// can be reported as a bad date rather than as arithmetic.
+pub fn is_date_shaped(text: &str) -> bool {
+    let date = text.split_once('T').map_or(text, |(date, _)| date);
+    date_parts(date).is_some()
+}
+
+/// Parses an ISO 8601 calendar date, `2024-04-07`, to days since the epoch.
+pub fn parse_date(text: &str) -> Option<i64> {
+    let (year, month, day) = date_parts(text)?;
+    let (year, month, day) = (year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+    match (1..=12).contains(&month) && (1..=days_in_month(year, month)).contains(&day) {
+        true => Some(days_from_civil(year, month, day)),
+        false => None,
+    }
+}
+
+/// Parses a date with an optional UTC time of day, `2024-04-07T09:30` or
+/// `2024-04-07T09:30:15Z`, to seconds since the epoch.
+pub fn parse_datetime(text: &str) -> Option<i64> {
+    let (date, time) = match text.split_once('T') {
+        Some((date, time)) => (date, parse_time(time.strip_suffix('Z').unwrap_or(time))?),
+        None => (text, 0),
+    };
+    Some(parse_date(date)? * SECONDS_PER_DAY + time)
+}
+
+fn parse_time(text: &str) -> Option<i64> {
+    let parts: Vec<&str> = text.split(':').collect();
+    let two_digits = |part: &&str| part.len() == 2 && part.bytes().all(|b| b.is_ascii_digit());
+    if !(2..=3).contains(&parts.len()) || !parts.iter().all(two_digits) {
+        return None;
+    }
+    let [hour, minute, second] =
+        [0, 1, 2].map(|i| parts.get(i).map_or(0, |p| p.parse().unwrap_or(0)));
+    match hour < 24 && minute < 60 && second < 60 {
+        true => Some(hour * 3600 + minute * 60 + second),
+        false => None,
+    }
+}
+
+pub fn iso_date(days: i64) -> String {
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Just the date at midnight, otherwise the time to the second.
+pub fn iso_datetime(seconds: i64) -> String {
+    let date = iso_date(seconds.div_euclid(SECONDS_PER_DAY));
+    match seconds.rem_euclid(SECONDS_PER_DAY) {
+        0 => date,
+        time => format!(
+            "{}T{:02}:{:02}:{:02}",
+            date,
+            time / 3600,
+            time / 60 % 60,
+            time % 60
+        ),
+    }
+}
+
+/// Parses a duration in seconds, either compact like `30d` and `1h30m` or
+/// ISO 8601 like `P1DT12H`, with an optional `-`.
+pub fn parse_duration(text: &str) -> Option<i64> {
+    let (sign, text) = match text.strip_prefix('-') {
+        Some(text) => (-1, text),
+        None => (1, text),
+    };
+    let seconds = match text.strip_prefix('P') {
+        Some(iso) => match iso.split_once('T') {
+            Some((_, "")) => None,
+            Some((days, time)) => {
+                sum_parts(days, ISO_DAYS)?.checked_add(sum_parts(time, ISO_TIME)?)
+            }
+            None if !iso.is_empty() => sum_parts(iso, ISO_DAYS),
+            None => None,
+        },
+        None if !text.is_empty() => sum_parts(text, COMPACT),
+        None => None,
+    };
+    Some(sign * seconds?)
+}
+
+/// Adds up numbers each followed by one of `units`, which must come in
+/// order and at most once.
+fn sum_parts(text: &str, units: &[(char, i64)]) -> Option<i64> {
+    let mut units = units.iter();
+    let mut rest = text;
+    let mut total: i64 = 0;
+    while !rest.is_empty() {
+        let end = rest
+            .find(|ch: char| !ch.is_ascii_digit())
+            .filter(|end| *end > 0)?;
+        let n: i64 = rest[..end].parse().ok()?;
+        let suffix = rest[end..].chars().next()?;
+        let (_, seconds) = units.find(|(unit, _)| *unit == suffix)?;
+        total = total.checked_add(n.checked_mul(*seconds)?)?;
+        rest = &rest[end + suffix.len_utf8()..];
+    }
+    Some(total)
+}
+
+/// The compact form, `1d12h` or `-90s`.
+pub fn duration_string(seconds: i64) -> String {
+    if seconds == 0 {
+        return "0s".into();
+    }
+    let mut text = String::from(if seconds < 0 { "-" } else { "" });
+    let mut rest = seconds.unsigned_abs();
+    for (suffix, size) in &COMPACT[1..] {
+        let size = *size as u64;
+        if rest >= size {
+            text += &format!("{}{}", rest / size, suffix);
+            rest %= size;
+        }
+    }
+    text
+}
+
+/// Seconds since the epoch by the system clock.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
+/// The current UTC date, in days since the epoch.
+pub fn today() -> i64 {
+    now().div_euclid(SECONDS_PER_DAY)
+}
+
+pub fn describe(n: &Number) -> String {
+    match n {
+        Number::Date(_) => "a date".into(),
+        Number::Duration(_) => "a duration".into(),
+        Number::Quantity(x) => format!("`{}`", x.unit_name()),
+        Number::Money(x) => format!("`{}`", x.currency),
+        Number::List(_) => "a list".into(),
+        Number::Int(_) | Number::Float(_) => "a plain number".into(),
+    }
+}
+
+fn plain(n: &Number) -> Option<f64> {
+    match n {
+        Number::Int(n) => Some(*n as f64),
+        Number::Float(n) => Some(*n as f64),
+        _ => None,
+    }
+}
+
+/// A duration scaled by a plain number, to the nearest second.
+fn seconds(scaled: f64) -> Option<Number> {
+    let scaled = scaled.round();
+    (scaled.is_finite() && scaled.abs() < i64::MAX as f64)
+        .then_some(Number::Duration(scaled as i64))
+}
+
+/// Arithmetic where at least one side is a date or a duration. A date moves
+/// by a duration, two dates are a duration apart, and durations add and
+/// scale by plain numbers.
+pub fn calc(op: Op, x: &Number, y: &Number) -> Result<Number, EvalError> {
+    let result = match (op, x, y) {
+        (Op::Add, Number::Date(a), Number::Duration(b))
+        | (Op::Add, Number::Duration(b), Number::Date(a)) => a.checked_add(*b).map(Number::Date),
+        (Op::Sub, Number::Date(a), Number::Duration(b)) => a.checked_sub(*b).map(Number::Date),
+        (Op::Sub, Number::Date(a), Number::Date(b)) => a.checked_sub(*b).map(Number::Duration),
+        (Op::Add, Number::Duration(a), Number::Duration(b)) => {
+            a.checked_add(*b).map(Number::Duration)
+        }
+        (Op::Sub, Number::Duration(a), Number::Duration(b)) => {
+            a.checked_sub(*b).map(Number::Duration)
+        }
+        (Op::Mult, Number::Duration(a), n) | (Op::Mult, n, Number::Duration(a))
+            if plain(n).is_some() =>
+        {
+            plain(n).and_then(|n| seconds(*a as f64 * n))
+        }
+        (Op::Div, Number::Duration(a), n) if plain(n).is_some() => {
+            plain(n).and_then(|n| seconds(*a as f64 / n))
+        }
+        (Op::Div, Number::Duration(a), Number::Duration(b)) => {
+            (*b != 0).then(|| Number::Float((*a as f64 / *b as f64) as f32))
+        }
+        _ => {
+            let operands = format!("{} and {}", describe(x), describe(y));
+            return Err(EvalError::TimeNotAllowed(op.to_string(), operands));
+        }
+    };
+    result.ok_or(EvalError::TimeOutOfRange)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_civil() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        for days in [-719468, -1, 0, 59, 60, 11016, 19820, 2932896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days, "{}", days);
+        }
+        assert_eq!(civil_from_days(19820), (2024, 4, 7));
+    }
+
+    #[test]
+    fn test_parse_datetime() {
+        let day = 19820 * SECONDS_PER_DAY;
+        assert_eq!(parse_datetime("2024-04-07"), Some(day));
+        assert_eq!(parse_datetime("2024-04-07T09:30"), Some(day + 34200));
+        assert_eq!(parse_datetime("2024-04-07T09:30:15Z"), Some(day + 34215));
+        assert_eq!(iso_datetime(day), "2024-04-07");
+        assert_eq!(iso_datetime(day + 34215), "2024-04-07T09:30:15");
+        assert_eq!(iso_datetime(-1), "1969-12-31T23:59:59");
+        for text in [
+            "2024-04-07T",
+            "2024-04-07T24:00",
+            "2024-04-07T9:30",
+            "2024-04-07T09:30:15:00",
+        ] {
+            assert_eq!(parse_datetime(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_duration() {
+        assert_eq!(parse_duration("30d"), Some(30 * SECONDS_PER_DAY));
+        assert_eq!(parse_duration("1h30m"), Some(5400));
+        assert_eq!(parse_duration("-2w"), Some(-14 * SECONDS_PER_DAY));
+        assert_eq!(parse_duration("P1DT12H"), Some(129600));
+        assert_eq!(parse_duration("PT90S"), Some(90));
+        assert_eq!(parse_duration("P2W"), Some(14 * SECONDS_PER_DAY));
+        for text in [
+            "", "d", "30", "1m1h", "1d1d", "30x", "P", "P1DT", "P1H", "P1M", "-",
+        ] {
+            assert_eq!(parse_duration(text), None, "{}", text);
+        }
+        assert_eq!(duration_string(129600), "1d12h");
+        assert_eq!(duration_string(14 * SECONDS_PER_DAY + 61), "14d1m1s");
+        assert_eq!(duration_string(-90), "-1m30s");
+        assert_eq!(duration_string(0), "0s");
+    }
+
+    #[test]
+    fn test_calc() {
+        let date = Number::Date(19820 * SECONDS_PER_DAY);
+        let month = Number::Duration(30 * SECONDS_PER_DAY);
+        let later = calc(Op::Add, &date, &month).unwrap();
+        assert_eq!(later.to_string(), "2024-05-07");
+        assert_eq!(calc(Op::Add, &month, &date), Ok(later.clone()));
+        assert_eq!(calc(Op::Sub, &later, &date), Ok(month.clone()));
+        assert_eq!(
+            calc(Op::Sub, &date, &month).unwrap().to_string(),
+            "2024-03-08"
+        );
+        assert_eq!(
+            calc(Op::Div, &month, &Number::Int(7)).unwrap().to_string(),
+            "4d6h51m26s"
+        );
+        assert_eq!(
+            calc(Op::Mult, &Number::Float(0.5), &month),
+            Ok(Number::Duration(15 * SECONDS_PER_DAY))
+        );
+        assert_eq!(
+            calc(Op::Div, &month, &Number::Duration(SECONDS_PER_DAY)),
+            Ok(Number::Float(30.0))
+        );
+        assert_eq!(
+            calc(Op::Add, &date, &date),
+            Err(EvalError::TimeNotAllowed(
+                "+".into(),
+                "a date and a date".into()
+            ))
+        );
+        assert_eq!(
+            calc(Op::Add, &date, &Number::Int(1)),
+            Err(EvalError::TimeNotAllowed(
+                "+".into(),
+                "a date and a plain number".into()
+            ))
+        );
+        assert_eq!(
+            calc(Op::Div, &month, &Number::Int(0)),
+            Err(EvalError::TimeOutOfRange)
+        );
+        assert_eq!(
+            calc(Op::Add, &Number::Date(i64::MAX), &month),
+            Err(EvalError::TimeOutOfRange)
+        );
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("2024-04-07"), Some(19820));
+        assert_eq!(parse_date("2024-02-29"), Some(19782));
+        assert_eq!(iso_date(19782), "2024-02-29");
+        for text in [
+            "2023-02-29",
+            "2024-13-01",
+            "2024-4-7",
+            "24-04-07",
+            "2024-04-07x",
+            "",
+        ] {
+            assert_eq!(parse_date(text), None, "{}", text);
+        }
+        assert!(is_date_shaped("2024-02-30"));
+        assert!(is_date_shaped("2024-04-07T25:00"));
+        assert!(!is_date_shaped("2024-4-7"));
+        assert!(!is_date_shaped("2024-04"));
+    }
+}
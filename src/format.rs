@@ -0,0 +1,275 @@
+use crate::interpreter::Number;
+
+const MAX_DIGITS: usize = 16;
+
+/// How many digits to show, the way the FIX/SCI/ENG keys work on a
+/// calculator. `Std` prints numbers as they are stored.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Notation {
+    Std,
+    /// Digits after the decimal point.
+    Fix(usize),
+    /// Digits after the decimal point of the mantissa.
+    Sci(usize),
+    /// Like `Sci` but the exponent is always a multiple of three.
+    Eng(usize),
+    /// Significant digits.
+    Sig(usize),
+}
+
+/// Controls how results are printed. Only the printed text is rounded, the
+/// stored values keep their full precision.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DisplayMode {
+    pub notation: Notation,
+    pub thousands: bool,
+    pub decimal_mark: char,
+}
+
+impl Default for DisplayMode {
+    fn default() -> Self {
+        DisplayMode {
+            notation: Notation::Std,
+            thousands: false,
+            decimal_mark: '.',
+        }
+    }
+}
+
+/// Builds a notation from its command name, `fix`, `sci`, `eng` or `sig`,
+/// and a number of digits.
+pub fn notation(name: &str, digits: &str) -> Option<Notation> {
+    let digits: usize = digits.parse().ok().filter(|n| *n <= MAX_DIGITS)?;
+    match name {
+        "fix" => Some(Notation::Fix(digits)),
+        "sci" => Some(Notation::Sci(digits)),
+        "eng" => Some(Notation::Eng(digits)),
+        "sig" if digits > 0 => Some(Notation::Sig(digits)),
+        _ => None,
+    }
+}
+
+impl DisplayMode {
+    pub fn format(&self, number: &Number) -> String {
+        if let Number::Quantity(x) = number {
+            let value = self.format(&Number::Float(x.shown()));
+            return format!("{} {}", value, x.unit_name());
+        }
+        // Always to the smallest coin, whatever the notation.
+        if let Number::Money(x) = number {
+            return format!("{} {}", self.punctuate(&x.shown()), x.currency);
+        }
+        if let Number::List(items) = number {
+            let items: Vec<String> = items.iter().map(|item| self.format(item)).collect();
+            return format!("[ {} ]", items.join(" "));
+        }
+        // ISO dates and `1d12h` whatever the notation.
+        if let Number::Date(_) | Number::Duration(_) = number {
+            return number.to_string();
+        }
+        let text = match (number, self.notation) {
+            (Number::Int(n), Notation::Std | Notation::Fix(_)) => n.to_string(),
+            (Number::Float(n), Notation::Std) => n.to_string(),
+            (number, notation) => format_float(to_f64(number), notation),
+        };
+        self.punctuate(&text)
+    }
+
+    /// Swaps in the decimal mark and groups the integer digits.
+    fn punctuate(&self, text: &str) -> String {
+        let (mantissa, exponent) = match text.find('e') {
+            Some(at) => text.split_at(at),
+            None => (text, ""),
+        };
+        let (int_part, frac_part) = match mantissa.find('.') {
+            Some(at) => (&mantissa[..at], Some(&mantissa[at + 1..])),
+            None => (mantissa, None),
+        };
+
+        let mut out = match self.thousands {
+            true => group(int_part, self.group_separator()),
+            false => int_part.to_string(),
+        };
+        if let Some(frac_part) = frac_part {
+            out.push(self.decimal_mark);
+            out.push_str(frac_part);
+        }
+        out.push_str(exponent);
+        out
+    }
+
+    fn group_separator(&self) -> char {
+        match self.decimal_mark {
+            ',' => '.',
+            _ => ',',
+        }
+    }
+}
+
+fn to_f64(number: &Number) -> f64 {
+    match number {
+        Number::Int(n) => *n as f64,
+        Number::Float(n) => *n as f64,
+        Number::Quantity(x) => x.shown() as f64,
+        Number::Money(x) => x.amount.to_f32() as f64,
+        Number::Date(x) | Number::Duration(x) => *x as f64,
+        Number::List(_) => f64::NAN,
+    }
+}
+
+fn format_float(x: f64, notation: Notation) -> String {
+    if !x.is_finite() {
+        return x.to_string();
+    }
+    match notation {
+        Notation::Std => x.to_string(),
+        Notation::Fix(digits) => format!("{:.*}", digits, x),
+        Notation::Sci(digits) => format!("{:.*e}", digits, x),
+        Notation::Eng(digits) => format_eng(x, digits),
+        Notation::Sig(digits) => format_sig(x, digits),
+    }
+}
+
+fn format_eng(x: f64, digits: usize) -> String {
+    if x == 0.0 {
+        return format!("{:.*}e0", digits, 0.0);
+    }
+    let mut exponent = x.abs().log10().floor() as i32;
+    exponent -= exponent.rem_euclid(3);
+    let mut mantissa = format!("{:.*}", digits, x / 10f64.powi(exponent));
+    // Rounding can carry into a fourth integer digit, e.g. 999.96 -> 1000.0
+    if mantissa
+        .trim_start_matches('-')
+        .find('.')
+        .unwrap_or(mantissa.len())
+        > 3
+    {
+        exponent += 3;
+        mantissa = format!("{:.*}", digits, x / 10f64.powi(exponent));
+    }
+    format!("{}e{}", mantissa, exponent)
+}
+
+fn format_sig(x: f64, digits: usize) -> String {
+    if x == 0.0 {
+        return format!("{:.*}", digits - 1, 0.0);
+    }
+    let magnitude = |x: f64| x.abs().log10().floor() as i32;
+    let scale = 10f64.powi(magnitude(x) - digits as i32 + 1);
+    let rounded = (x / scale).round() * scale;
+    let decimals = (digits as i32 - 1 - magnitude(rounded)).max(0) as usize;
+    format!("{:.*}", decimals, rounded)
+}
+
+/// Inserts `separator` between groups of three digits, leaving any sign alone.
+fn group(int_part: &str, separator: char) -> String {
+    let (sign, digits) = match int_part.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", int_part),
+    };
+    let mut grouped = String::from(sign);
+    for (i, ch) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(separator);
+        }
+        grouped.push(ch);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mode(notation: Notation) -> DisplayMode {
+        DisplayMode {
+            notation,
+            ..DisplayMode::default()
+        }
+    }
+
+    #[test]
+    fn test_notation() {
+        assert_eq!(notation("fix", "4"), Some(Notation::Fix(4)));
+        assert_eq!(notation("sig", "0"), None);
+        assert_eq!(notation("sci", "x"), None);
+        assert_eq!(notation("sci", "99"), None);
+        assert_eq!(notation("std", "2"), None);
+    }
+
+    #[test]
+    fn test_format() {
+        let std = DisplayMode::default();
+        assert_eq!(std.format(&Number::Float(1.1 * 3.0)), "3.3000002");
+        assert_eq!(std.format(&Number::Int(-1234567)), "-1234567");
+
+        assert_eq!(
+            mode(Notation::Fix(2)).format(&Number::Float(1.1 * 3.0)),
+            "3.30"
+        );
+        assert_eq!(mode(Notation::Fix(0)).format(&Number::Float(2.5)), "2");
+        assert_eq!(mode(Notation::Fix(2)).format(&Number::Int(7)), "7");
+
+        assert_eq!(
+            mode(Notation::Sci(3)).format(&Number::Float(12346.0)),
+            "1.235e4"
+        );
+        assert_eq!(mode(Notation::Sci(2)).format(&Number::Int(-5)), "-5.00e0");
+
+        assert_eq!(
+            mode(Notation::Eng(2)).format(&Number::Float(12346.0)),
+            "12.35e3"
+        );
+        assert_eq!(
+            mode(Notation::Eng(1)).format(&Number::Float(0.00012)),
+            "120.0e-6"
+        );
+        assert_eq!(
+            mode(Notation::Eng(1)).format(&Number::Float(999.96)),
+            "1.0e3"
+        );
+        assert_eq!(mode(Notation::Eng(1)).format(&Number::Int(0)), "0.0e0");
+
+        assert_eq!(mode(Notation::Sig(3)).format(&Number::Float(0.3)), "0.300");
+        assert_eq!(
+            mode(Notation::Sig(3)).format(&Number::Int(123456)),
+            "123000"
+        );
+        assert_eq!(mode(Notation::Sig(2)).format(&Number::Float(9.99)), "10");
+        assert_eq!(
+            mode(Notation::Sig(2)).format(&Number::Float(-0.001234)),
+            "-0.0012"
+        );
+
+        assert_eq!(
+            mode(Notation::Fix(2)).format(&Number::Float(f32::INFINITY)),
+            "inf"
+        );
+    }
+
+    #[test]
+    fn test_punctuation() {
+        let grouped = DisplayMode {
+            thousands: true,
+            ..DisplayMode::default()
+        };
+        assert_eq!(grouped.format(&Number::Int(-1234567)), "-1,234,567");
+        assert_eq!(grouped.format(&Number::Int(123)), "123");
+        assert_eq!(grouped.format(&Number::Float(1234.5)), "1,234.5");
+
+        let european = DisplayMode {
+            notation: Notation::Fix(2),
+            thousands: true,
+            decimal_mark: ',',
+        };
+        assert_eq!(european.format(&Number::Float(1234.5)), "1.234,50");
+        assert_eq!(
+            DisplayMode {
+                notation: Notation::Sci(1),
+                ..european
+            }
+            .format(&Number::Float(1234.5)),
+            "1,2e3"
+        );
+    }
+}
@@ -0,0 +1,361 @@
+use std::fmt::Display;
+
+use crate::dates;
+use crate::money::Currency;
+use crate::parser::ParserError;
+use crate::tokenizer::{self, BuiltinKind, Func, Op, Token};
+use crate::units::Unit;
+
+#[derive(Debug, PartialEq)]
+pub enum InfixError {
+    UnexpectedToken(String),
+    UnexpectedEnd,
+    UnbalancedParens,
+    UnknownFunction(String),
+    ArgumentCount(Func, usize),
+}
+
+impl InfixError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            InfixError::UnexpectedToken(_) => "E0020",
+            InfixError::UnexpectedEnd => "E0021",
+            InfixError::UnbalancedParens => "E0022",
+            InfixError::UnknownFunction(_) => "E0023",
+            InfixError::ArgumentCount(_, _) => "E0024",
+        }
+    }
+}
+
+impl Display for InfixError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InfixError::UnexpectedToken(text) => write!(f, "unexpected `{}`", text),
+            InfixError::UnexpectedEnd => {
+                write!(f, "the expression ends where a value was expected")
+            }
+            InfixError::UnbalancedParens => write!(f, "parentheses are not balanced"),
+            InfixError::UnknownFunction(name) => write!(f, "`{}` is not a function", name),
+            InfixError::ArgumentCount(func, found) => write!(
+                f,
+                "`{}` takes {} argument{}, not {}",
+                func,
+                func.arity(),
+                if func.arity() == 1 { "" } else { "s" },
+                found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InfixError {}
+
+#[derive(Debug, PartialEq)]
+enum Lexeme {
+    Value(String),
+    Call(String),
+    Operator(char),
+    Open,
+    Close,
+    Comma,
+    Separator,
+}
+
+fn lex(text: &str) -> Result<Vec<Lexeme>, InfixError> {
+    let mut lexemes = vec![];
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, ch)) = chars.next() {
+        match ch {
+            ch if ch.is_whitespace() => {}
+            '+' | '-' | '*' | '/' | '^' | '!' => lexemes.push(Lexeme::Operator(ch)),
+            '(' => lexemes.push(Lexeme::Open),
+            ')' => lexemes.push(Lexeme::Close),
+            ',' => lexemes.push(Lexeme::Comma),
+            ';' => lexemes.push(Lexeme::Separator),
+            ch if ch.is_ascii_digit() && date_length(&text[start..]).is_some() => {
+                let end = start + date_length(&text[start..]).unwrap();
+                while chars.next_if(|&(at, _)| at < end).is_some() {}
+                lexemes.push(Lexeme::Value(text[start..end].into()));
+            }
+            ch if ch.is_alphanumeric() || matches!(ch, '_' | '.' | '$') => {
+                let mut end = start + ch.len_utf8();
+                while let Some(&(at, ch)) = chars.peek() {
+                    if !(ch.is_alphanumeric() || matches!(ch, '_' | '.')) {
+                        break;
+                    }
+                    end = at + ch.len_utf8();
+                    chars.next();
+                }
+                let word = text[start..end].to_string();
+                let rest = text[end..].trim_start();
+                if rest.starts_with('(')
+                    && word.starts_with(|ch: char| ch.is_alphabetic() || ch == '_')
+                {
+                    // The call takes the place of the open parenthesis.
+                    chars.find(|&(_, ch)| ch == '(');
+                    lexemes.push(Lexeme::Call(word));
+                } else {
+                    lexemes.push(Lexeme::Value(word));
+                }
+            }
+            ch => return Err(InfixError::UnexpectedToken(ch.to_string())),
+        }
+    }
+    Ok(lexemes)
+}
+
+/// How much of `text` is a date, which has `-` in it so is read whole before
+/// anything else: `2024-04-07` optionally followed by a time.
+fn date_length(text: &str) -> Option<usize> {
+    [20, 19, 17, 16, 10].into_iter().find(|&len| {
+        text.get(..len)
+            .is_some_and(|date| dates::parse_datetime(date).is_some())
+            && !text[len..].starts_with(|ch: char| ch.is_alphanumeric() || ch == '_' || ch == '.')
+    })
+}
+
+/// Operators waiting for their right hand side, and open parentheses.
+#[derive(Debug)]
+enum Pending {
+    Binary(Op),
+    Neg,
+    Open,
+    /// A function call's open parenthesis and the arguments seen so far.
+    Call(Func, usize),
+}
+
+impl Pending {
+    fn precedence(&self) -> u8 {
+        match self {
+            Pending::Binary(Op::Add | Op::Sub) => 1,
+            Pending::Binary(Op::Mult | Op::Div) => 2,
+            Pending::Neg => 3,
+            Pending::Binary(Op::Pow) => 4,
+            Pending::Open | Pending::Call(_, _) => 0,
+        }
+    }
+
+    fn token(&self) -> Option<Token> {
+        match self {
+            Pending::Binary(op) => Some(Token::Operation(*op)),
+            Pending::Neg => Some(Token::Function(Func::Neg)),
+            Pending::Open | Pending::Call(_, _) => None,
+        }
+    }
+}
+
+fn binary(ch: char) -> Op {
+    match ch {
+        '+' => Op::Add,
+        '-' => Op::Sub,
+        '*' => Op::Mult,
+        '/' => Op::Div,
+        _ => Op::Pow,
+    }
+}
+
+/// Builtin functions, units and currencies, so `km(5)` is five kilometres.
+fn function(name: &str) -> Result<Func, InfixError> {
+    match tokenizer::builtin(name).map(|builtin| builtin.kind) {
+        Some(BuiltinKind::Function(func)) => Ok(func),
+        _ => match (Unit::parse(name), Currency::parse(name)) {
+            (Some(unit), _) => Ok(Func::Unit(unit)),
+            (_, Some(currency)) => Ok(Func::Currency(currency)),
+            _ => Err(InfixError::UnknownFunction(name.into())),
+        },
+    }
+}
+
+/// Moves pending operators to the output until an open parenthesis.
+fn pop_operators(pending: &mut Vec<Pending>, output: &mut Vec<Token>) {
+    while let Some(token) = pending.last().and_then(Pending::token) {
+        pending.pop();
+        output.push(token);
+    }
+}
+
+/// Rewrites an infix expression, like `(3 + 4) * 2 ^ 3`, as the equivalent
+/// RPN tokens using the shunting-yard algorithm. `^` binds tightest and to
+/// the right, then unary minus, then `*` and `/`, then `+` and `-`.
+pub fn to_rpn(text: &str) -> Result<Vec<Token>, InfixError> {
+    let mut output = vec![];
+    let mut pending: Vec<Pending> = vec![];
+    // Whether the next lexeme should start a value rather than follow one.
+    let mut operand = true;
+
+    for lexeme in lex(text)? {
+        match (lexeme, operand) {
+            (Lexeme::Value(word), true) => {
+                let token = Token::from_word(&word);
+                let operator = match &token {
+                    Token::Function(func) => func.arity() > 0,
+                    token => matches!(token, Token::Operation(_)),
+                };
+                if operator {
+                    return Err(InfixError::UnexpectedToken(word));
+                }
+                output.push(token);
+                operand = false;
+            }
+            (Lexeme::Call(name), true) => pending.push(Pending::Call(function(&name)?, 1)),
+            (Lexeme::Open, true) => pending.push(Pending::Open),
+            (Lexeme::Operator('-'), true) => pending.push(Pending::Neg),
+            (Lexeme::Operator('+'), true) => {}
+            // Postfix, so it applies to the value just finished, before any
+            // operator still pending: `-3!` is `-(3!)` and `2^3!` is `2^(3!)`.
+            (Lexeme::Operator('!'), false) => output.push(Token::Function(Func::Factorial)),
+            (Lexeme::Operator(ch), false) if ch != '!' => {
+                let op = Pending::Binary(binary(ch));
+                // Everything but `^` groups to the left.
+                while let Some(top) = pending.last() {
+                    let higher = top.precedence() > op.precedence()
+                        || (top.precedence() == op.precedence() && ch != '^');
+                    match top.token() {
+                        Some(token) if higher => {
+                            pending.pop();
+                            output.push(token);
+                        }
+                        _ => break,
+                    }
+                }
+                pending.push(op);
+                operand = true;
+            }
+            (Lexeme::Comma, false) => {
+                pop_operators(&mut pending, &mut output);
+                match pending.last_mut() {
+                    Some(Pending::Call(_, args)) => *args += 1,
+                    _ => return Err(InfixError::UnexpectedToken(",".into())),
+                }
+                operand = true;
+            }
+            (Lexeme::Close, false) => {
+                pop_operators(&mut pending, &mut output);
+                match pending.pop() {
+                    Some(Pending::Open) => {}
+                    Some(Pending::Call(func, args)) if args == func.arity() => {
+                        output.push(Token::Function(func))
+                    }
+                    Some(Pending::Call(func, args)) => {
+                        return Err(InfixError::ArgumentCount(func, args))
+                    }
+                    _ => return Err(InfixError::UnbalancedParens),
+                }
+            }
+            (Lexeme::Separator, _) if operand && pending.is_empty() => {
+                output.push(Token::Separator)
+            }
+            (Lexeme::Separator, false) => {
+                pop_operators(&mut pending, &mut output);
+                if !pending.is_empty() {
+                    return Err(InfixError::UnbalancedParens);
+                }
+                output.push(Token::Separator);
+                operand = true;
+            }
+            (lexeme, _) => {
+                return Err(InfixError::UnexpectedToken(match lexeme {
+                    Lexeme::Value(word) | Lexeme::Call(word) => word,
+                    Lexeme::Operator(ch) => ch.to_string(),
+                    Lexeme::Open => "(".into(),
+                    Lexeme::Close => ")".into(),
+                    Lexeme::Comma => ",".into(),
+                    Lexeme::Separator => ";".into(),
+                }))
+            }
+        }
+    }
+
+    if operand && !pending.is_empty() {
+        return Err(InfixError::UnexpectedEnd);
+    }
+    pop_operators(&mut pending, &mut output);
+    match pending.is_empty() {
+        true => Ok(output),
+        false => Err(InfixError::UnbalancedParens),
+    }
+}
+
+impl From<InfixError> for ParserError {
+    fn from(value: InfixError) -> Self {
+        ParserError::Infix(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tokenizer::Field;
+
+    fn rpn(text: &str) -> Result<String, InfixError> {
+        Ok(to_rpn(text)?
+            .iter()
+            .map(Token::to_string)
+            .collect::<Vec<_>>()
+            .join(" "))
+    }
+
+    #[test]
+    fn test_to_rpn() {
+        assert_eq!(rpn("(3 + 4) * 2 ^ 3"), Ok("3 4 + 2 3 ^ *".into()));
+        assert_eq!(rpn("1 - 2 - 3"), Ok("1 2 - 3 -".into()));
+        assert_eq!(rpn("2 ^ 3 ^ 2"), Ok("2 3 2 ^ ^".into()));
+        assert_eq!(rpn("1 + 2 * 3 / 4"), Ok("1 2 3 * 4 / +".into()));
+        assert_eq!(rpn("-2 ^ 2"), Ok("2 2 ^ neg".into()));
+        assert_eq!(rpn("2 ^ -1"), Ok("2 1 neg ^".into()));
+        assert_eq!(rpn("-x * 3"), Ok("x neg 3 *".into()));
+        assert_eq!(rpn("--1 + +2"), Ok("1 neg neg 2 +".into()));
+        assert_eq!(rpn("sqrt(2) * pi"), Ok("2 sqrt 3.1415927 *".into()));
+        assert_eq!(rpn("sqrt((1 + 3) * 4)"), Ok("1 3 + 4 * sqrt".into()));
+        assert_eq!(rpn("1.5*2; 3"), Ok("1.5 2 * ; 3".into()));
+        assert_eq!(rpn(""), Ok("".into()));
+        assert_eq!(
+            rpn("2024-04-07 + 30d - 2024-04-07T09:30:00Z"),
+            Ok("2024-04-07 30d + 2024-04-07T09:30:00 -".into())
+        );
+        assert_eq!(rpn("2024-04-07-1d"), Ok("2024-04-07 1d -".into()));
+        assert_eq!(rpn("unix(today) - 2024"), Ok("today unix 2024 -".into()));
+        assert_eq!(rpn("-2 ^ 3! + 1"), Ok("2 3 ! ^ neg 1 +".into()));
+        assert_eq!(rpn("(1 + 2)!!"), Ok("1 2 + ! !".into()));
+        assert_eq!(rpn("ncr(5, 2) * 2"), Ok("5 2 ncr 2 *".into()));
+        assert_eq!(
+            to_rpn("$qty * $2"),
+            Ok(vec![
+                Token::Field(Field::Name("qty".into())),
+                Token::Field(Field::Index(2)),
+                Token::Operation(Op::Mult)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_to_rpn_errors() {
+        assert_eq!(rpn("3 4"), Err(InfixError::UnexpectedToken("4".into())));
+        assert_eq!(rpn("2 (3)"), Err(InfixError::UnexpectedToken("(".into())));
+        assert_eq!(rpn("1 * / 2"), Err(InfixError::UnexpectedToken("/".into())));
+        assert_eq!(rpn("1 % 2"), Err(InfixError::UnexpectedToken("%".into())));
+        assert_eq!(rpn("!3"), Err(InfixError::UnexpectedToken("!".into())));
+        assert_eq!(
+            rpn("sqrt + 1"),
+            Err(InfixError::UnexpectedToken("sqrt".into()))
+        );
+        assert_eq!(rpn("1 +"), Err(InfixError::UnexpectedEnd));
+        assert_eq!(rpn("(1 + 2"), Err(InfixError::UnbalancedParens));
+        assert_eq!(rpn("1 + 2)"), Err(InfixError::UnbalancedParens));
+        assert_eq!(rpn("(1; 2)"), Err(InfixError::UnbalancedParens));
+        assert_eq!(rpn("1, 2"), Err(InfixError::UnexpectedToken(",".into())));
+        assert_eq!(
+            rpn("foo(1)"),
+            Err(InfixError::UnknownFunction("foo".into()))
+        );
+        assert_eq!(
+            rpn("sqrt(1, 2)"),
+            Err(InfixError::ArgumentCount(Func::Sqrt, 2))
+        );
+        assert_eq!(rpn("sqrt()"), Err(InfixError::UnexpectedToken(")".into())));
+        assert_eq!(
+            InfixError::ArgumentCount(Func::Sqrt, 2).to_string(),
+            "`sqrt` takes 1 argument, not 2"
+        );
+    }
+}
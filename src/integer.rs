@@ -0,0 +1,372 @@
+use crate::interpreter::{EvalError, Number};
+use crate::tokenizer::Func;
+
+/// Bases that make Miller-Rabin exact for every 64 bit number.
+const WITNESSES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+/// A whole number argument. Floats are accepted when they have no
+/// fractional part.
+fn whole(func: Func, n: &Number) -> Result<i64, EvalError> {
+    let x = match n {
+        Number::Int(x) => {
+            return i64::try_from(*x).map_err(|_| EvalError::OutOfDomain(func, n.clone()))
+        }
+        n => n.plain(func)?,
+    };
+    if x.fract() != 0.0 {
+        return Err(EvalError::NotAnInteger(func, n.clone()));
+    }
+    match x.abs() < i64::MAX as f32 {
+        true => Ok(x as i64),
+        false => Err(EvalError::OutOfDomain(func, n.clone())),
+    }
+}
+
+/// The result unless working it out overflowed, which is an error rather
+/// than a rounded float, like other integer arithmetic.
+fn exact(func: Func, result: Option<i128>) -> Result<Number, EvalError> {
+    result
+        .map(Number::Int)
+        .ok_or_else(|| EvalError::IntegerOverflow(func.to_string()))
+}
+
+fn gcd_of(a: i128, b: i128) -> i128 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+pub fn factorial(n: &Number) -> Result<Number, EvalError> {
+    let n = match whole(Func::Factorial, n)? {
+        k if k < 0 => return Err(EvalError::OutOfDomain(Func::Factorial, n.clone())),
+        k => k,
+    };
+    let product = (1..=n as i128).try_fold(1i128, |product, k| product.checked_mul(k));
+    exact(Func::Factorial, product)
+}
+
+/// Ways to choose `k` of `n` things, ignoring their order.
+pub fn ncr(n: &Number, k: &Number) -> Result<Number, EvalError> {
+    if whole(Func::Ncr, n)? < 0 {
+        return Err(EvalError::OutOfDomain(Func::Ncr, n.clone()));
+    }
+    let (n, k) = (whole(Func::Ncr, n)?, whole(Func::Ncr, k)?);
+    if k < 0 || k > n {
+        return Ok(Number::Int(0));
+    }
+    let (n, k) = (n as i128, k.min(n - k) as i128);
+    // C(n, i + 1) = C(n, i) (n - i) / (i + 1), divided out first so only a
+    // result that is itself too large overflows.
+    let choices = (0..k).try_fold(1i128, |c, i| {
+        let g = gcd_of(c, i + 1);
+        (c / g).checked_mul((n - i) / ((i + 1) / g))
+    });
+    exact(Func::Ncr, choices)
+}
+
+/// Ways to arrange `k` of `n` things in order.
+pub fn npr(n: &Number, k: &Number) -> Result<Number, EvalError> {
+    if whole(Func::Npr, n)? < 0 {
+        return Err(EvalError::OutOfDomain(Func::Npr, n.clone()));
+    }
+    let (n, k) = (whole(Func::Npr, n)?, whole(Func::Npr, k)?);
+    if k < 0 || k > n {
+        return Ok(Number::Int(0));
+    }
+    let (n, k) = (n as i128, k as i128);
+    let product = (n - k + 1..=n).try_fold(1i128, |p, factor| p.checked_mul(factor));
+    exact(Func::Npr, product)
+}
+
+pub fn gcd(a: &Number, b: &Number) -> Result<Number, EvalError> {
+    let (a, b) = (whole(Func::Gcd, a)?, whole(Func::Gcd, b)?);
+    Ok(Number::Int(gcd_of(a as i128, b as i128)))
+}
+
+pub fn lcm(a: &Number, b: &Number) -> Result<Number, EvalError> {
+    let (a, b) = (whole(Func::Lcm, a)? as i128, whole(Func::Lcm, b)? as i128);
+    Ok(match a == 0 || b == 0 {
+        true => Number::Int(0),
+        false => Number::Int((a / gcd_of(a, b) * b).abs()),
+    })
+}
+
+fn mul_mod(a: u64, b: u64, m: u64) -> u64 {
+    (a as u128 * b as u128 % m as u128) as u64
+}
+
+fn pow_mod(mut base: u64, mut exp: u64, m: u64) -> u64 {
+    let mut result = 1 % m;
+    base %= m;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul_mod(result, base, m);
+        }
+        base = mul_mod(base, base, m);
+        exp >>= 1;
+    }
+    result
+}
+
+/// Miller-Rabin with enough bases to never be wrong below 2^64.
+fn is_prime(n: u64) -> bool {
+    if n < 2 {
+        return false;
+    }
+    if let Some(&p) = WITNESSES.iter().find(|&&p| n.is_multiple_of(p)) {
+        return n == p;
+    }
+    let (mut d, mut s) = (n - 1, 0);
+    while d.is_multiple_of(2) {
+        d /= 2;
+        s += 1;
+    }
+    WITNESSES.iter().all(|&a| {
+        let mut x = pow_mod(a, d, n);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        (1..s).any(|_| {
+            x = mul_mod(x, x, n);
+            x == n - 1
+        })
+    })
+}
+
+/// 1 for a prime and 0 otherwise.
+pub fn isprime(n: &Number) -> Result<Number, EvalError> {
+    let n = whole(Func::IsPrime, n)?;
+    Ok(Number::Int(i128::from(n > 0 && is_prime(n as u64))))
+}
+
+/// A factor of `n`, an odd composite, other than 1 and `n`, by Pollard's
+/// rho method with Floyd's cycle finding.
+fn rho(n: u64) -> u64 {
+    (1..)
+        .find_map(|c| {
+            let step = |x: u64| (mul_mod(x, x, n) + c) % n;
+            let (mut x, mut y, mut d) = (2, 2, 1);
+            while d == 1 {
+                x = step(x);
+                y = step(step(y));
+                d = gcd_of(x.abs_diff(y).into(), n.into()) as u64;
+            }
+            // Both walks met without finding a factor, so try another step.
+            (d != n).then_some(d)
+        })
+        .expect("a composite has a factor")
+}
+
+fn prime_factors(n: u64, factors: &mut Vec<u64>) {
+    match n {
+        1 => {}
+        n if is_prime(n) => factors.push(n),
+        n => {
+            let d = rho(n);
+            prime_factors(d, factors);
+            prime_factors(n / d, factors);
+        }
+    }
+}
+
+/// The prime factors of a positive number, smallest first and repeated, so
+/// `12` is `[ 2 2 3 ]`.
+pub fn factor(n: &Number) -> Result<Number, EvalError> {
+    let mut rest = match whole(Func::Factor, n)? {
+        k if k < 1 => return Err(EvalError::OutOfDomain(Func::Factor, n.clone())),
+        k => k as u64,
+    };
+    // Small primes are divided out by trial, which also leaves rho an odd
+    // number, and anything left is split by rho so large semiprimes don't
+    // take billions of divisions.
+    let mut factors = vec![];
+    for p in WITNESSES {
+        while rest % p == 0 {
+            factors.push(p);
+            rest /= p;
+        }
+    }
+    prime_factors(rest, &mut factors);
+    factors.sort_unstable();
+    Ok(Number::List(
+        factors.into_iter().map(|p| Number::Int(p.into())).collect(),
+    ))
+}
+
+/// The modulus of `modpow` and `modinv`, which must be positive.
+fn modulus(func: Func, m: &Number) -> Result<u64, EvalError> {
+    match whole(func, m)? {
+        m if m > 0 => Ok(m as u64),
+        _ => Err(EvalError::OutOfDomain(func, m.clone())),
+    }
+}
+
+/// The `x` in `[0, m)` where `a x` is 1 modulo `m`, by the extended
+/// Euclidean algorithm.
+fn inverse(a: i64, m: u64) -> Option<u64> {
+    let m = m as i128;
+    let (mut r0, mut r1) = (m, (a as i128).rem_euclid(m));
+    let (mut t0, mut t1) = (0i128, 1i128);
+    while r1 != 0 {
+        let q = r0 / r1;
+        (r0, r1) = (r1, r0 - q * r1);
+        (t0, t1) = (t1, t0 - q * t1);
+    }
+    (r0 == 1).then(|| t0.rem_euclid(m) as u64)
+}
+
+pub fn modinv(a: &Number, m: &Number) -> Result<Number, EvalError> {
+    let (a, m) = (whole(Func::ModInv, a)?, modulus(Func::ModInv, m)?);
+    match inverse(a, m) {
+        Some(x) => Ok(Number::Int(x as i128)),
+        None => Err(EvalError::NoInverse(a, m as i64)),
+    }
+}
+
+/// `b` to the power of `e` modulo `m`, a negative power being one of the
+/// inverse.
+pub fn modpow(b: &Number, e: &Number, m: &Number) -> Result<Number, EvalError> {
+    let (b, e) = (whole(Func::ModPow, b)?, whole(Func::ModPow, e)?);
+    let m = modulus(Func::ModPow, m)?;
+    let base = match e < 0 {
+        true => inverse(b, m).ok_or(EvalError::NoInverse(b, m as i64))?,
+        false => (b as i128).rem_euclid(m as i128) as u64,
+    };
+    Ok(Number::Int(pow_mod(base, e.unsigned_abs(), m) as i128))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ints(values: &[i128]) -> Vec<Number> {
+        values.iter().map(|n| Number::Int(*n)).collect()
+    }
+
+    #[test]
+    fn test_factorial() {
+        let factorial = |n| factorial(&Number::Int(n));
+        assert_eq!(factorial(0), Ok(Number::Int(1)));
+        assert_eq!(factorial(12), Ok(Number::Int(479001600)));
+        assert_eq!(factorial(13), Ok(Number::Int(6227020800)));
+        assert_eq!(
+            factorial(33),
+            Ok(Number::Int(8683317618811886495518194401280000000))
+        );
+        assert_eq!(factorial(34), Err(EvalError::IntegerOverflow("!".into())));
+        assert_eq!(factorial(40), Err(EvalError::IntegerOverflow("!".into())));
+        assert_eq!(
+            factorial(-3),
+            Err(EvalError::OutOfDomain(Func::Factorial, Number::Int(-3)))
+        );
+        assert_eq!(super::factorial(&Number::Float(5.0)), Ok(Number::Int(120)));
+        assert_eq!(
+            super::factorial(&Number::Float(2.5)),
+            Err(EvalError::NotAnInteger(Func::Factorial, Number::Float(2.5)))
+        );
+    }
+
+    #[test]
+    fn test_combinations() {
+        let [a, b, c, d] = [10, 3, 67, 33].map(Number::Int);
+        assert_eq!(ncr(&a, &b), Ok(Number::Int(120)));
+        assert_eq!(npr(&a, &b), Ok(Number::Int(720)));
+        assert_eq!(ncr(&b, &a), Ok(Number::Int(0)));
+        assert_eq!(npr(&a, &Number::Int(-1)), Ok(Number::Int(0)));
+        let largest = Number::Int(i64::MAX.into());
+        assert_eq!(npr(&largest, &Number::Int(0)), Ok(Number::Int(1)));
+        assert_eq!(ncr(&largest, &Number::Int(0)), Ok(Number::Int(1)));
+        assert_eq!(ncr(&largest, &largest), Ok(Number::Int(1)));
+        assert_eq!(ncr(&c, &d), Ok(Number::Int(14226520737620288370)));
+        assert_eq!(
+            npr(&Number::Int(20), &Number::Int(20)),
+            Ok(Number::Int(2432902008176640000))
+        );
+        assert_eq!(
+            ncr(&Number::Int(1000), &Number::Int(500)),
+            Err(EvalError::IntegerOverflow("ncr".into()))
+        );
+        assert_eq!(
+            npr(&Number::Int(1000), &Number::Int(500)),
+            Err(EvalError::IntegerOverflow("npr".into()))
+        );
+        assert_eq!(
+            ncr(&Number::Int(-1), &b),
+            Err(EvalError::OutOfDomain(Func::Ncr, Number::Int(-1)))
+        );
+    }
+
+    #[test]
+    fn test_number_theory() {
+        let [a, b] = [12, -18].map(Number::Int);
+        assert_eq!(gcd(&a, &b), Ok(Number::Int(6)));
+        assert_eq!(lcm(&a, &b), Ok(Number::Int(36)));
+        assert_eq!(lcm(&a, &Number::Int(0)), Ok(Number::Int(0)));
+
+        let primes: Vec<i128> = (0..30)
+            .filter(|n| isprime(&Number::Int(*n)) == Ok(Number::Int(1)))
+            .collect();
+        assert_eq!(primes, vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29]);
+        assert_eq!(isprime(&Number::Int(2147483647)), Ok(Number::Int(1)));
+        assert_eq!(isprime(&Number::Int(-7)), Ok(Number::Int(0)));
+        assert!(is_prime(18446744073709551557));
+        assert!(!is_prime(3215031751));
+
+        assert_eq!(factor(&a), Ok(Number::List(ints(&[2, 2, 3]))));
+        assert_eq!(factor(&Number::Int(1)), Ok(Number::List(vec![])));
+        assert_eq!(
+            factor(&Number::Int(2147483646)),
+            Ok(Number::List(ints(&[2, 3, 3, 7, 11, 31, 151, 331])))
+        );
+        assert_eq!(
+            factor(&Number::Int(9223371994482243049)),
+            Ok(Number::List(ints(&[3037000493, 3037000493])))
+        );
+        assert_eq!(
+            factor(&Number::Int(9223371873002223329)),
+            Ok(Number::List(ints(&[3037000453, 3037000493])))
+        );
+        assert_eq!(
+            factor(&Number::Int(i64::MAX.into())),
+            Ok(Number::List(ints(&[7, 7, 73, 127, 337, 92737, 649657])))
+        );
+        assert_eq!(
+            factor(&b),
+            Err(EvalError::OutOfDomain(Func::Factor, Number::Int(-18)))
+        );
+
+        let [three, four, seven, eleven] = [3, 4, 7, 11].map(Number::Int);
+        assert_eq!(
+            modpow(&four, &Number::Int(13), &Number::Int(497)),
+            Ok(Number::Int(445))
+        );
+        assert_eq!(modinv(&three, &eleven), Ok(Number::Int(4)));
+        assert_eq!(modinv(&Number::Int(-3), &eleven), Ok(Number::Int(7)));
+        assert_eq!(
+            modpow(&three, &Number::Int(-1), &eleven),
+            Ok(Number::Int(4))
+        );
+        assert_eq!(
+            modpow(
+                &Number::Int(2147483647),
+                &Number::Int(2147483647),
+                &Number::Int(2147483629)
+            ),
+            Ok(Number::Int(pow_mod(18, 2147483647, 2147483629).into()))
+        );
+        assert_eq!(
+            modinv(&four, &Number::Int(8)),
+            Err(EvalError::NoInverse(4, 8))
+        );
+        assert_eq!(
+            modinv(&four, &Number::Int(0)),
+            Err(EvalError::OutOfDomain(Func::ModInv, Number::Int(0)))
+        );
+        assert_eq!(
+            modpow(&seven, &Number::Int(0), &Number::Int(1)),
+            Ok(Number::Int(0))
+        );
+    }
+}
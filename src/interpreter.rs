@@ -1,24 +1,252 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Display;
 
-use crate::parser::{Expr, ParserError};
-use crate::tokenizer::{Op, Token};
+use crate::dates;
+use crate::integer;
+use crate::money::{self, Money, MoneyError, Rates};
+use crate::numeric::{self, Start};
+use crate::parser::{self, Expr, ParserError};
+use crate::stats::{self, Stats};
+use crate::symbolic;
+use crate::tokenizer::{Field, Func, Op, Token};
+use crate::units::{self, Dimension, Quantity, Unit, DIMENSIONLESS};
 
 #[derive(Debug, PartialEq)]
 pub enum EvalError {
     UnexpectedLiteral(Token),
+    UnboundSymbol(String),
+    NothingToRecall,
+    MissingField(Field),
+    NotANumber(Field, String),
+    /// The undefined variables in a quotation, when there isn't exactly one.
+    FreeVariable(Func, Vec<String>),
+    NoConvergence(usize),
+    /// A bound for `sum` or `product` that isn't a whole number.
+    RangeBound(Func, Number),
+    /// Quantities that were added, subtracted or converted without
+    /// measuring the same thing.
+    DimensionMismatch(Dimension, Dimension),
+    /// An operation or function given a quantity it has no meaning for.
+    UnitsNotAllowed(String, Dimension),
+    Money(MoneyError),
+    /// An operation or function and the dates or durations it was given.
+    TimeNotAllowed(String, String),
+    TimeOutOfRange,
+    /// A statistics function and how many samples it needs.
+    NotEnoughSamples(Func, usize),
+    /// An operation or function given a list it can't take.
+    ListNotAllowed(String),
+    /// An integer function given a number with a fractional part.
+    NotAnInteger(Func, Number),
+    /// An integer function given a whole number it has no answer for, like
+    /// a negative factorial.
+    OutOfDomain(Func, Number),
+    /// A number and the modulus it has no inverse for.
+    NoInverse(i64, i64),
+    /// An integer operation or function whose result doesn't fit.
+    IntegerOverflow(String),
+    DivisionByZero,
+    /// A `sum` or `product` over more terms than `MAX_TERMS`.
+    TooManyTerms(Func),
+    /// An operation whose result would have a unit raised to a power
+    /// beyond what a quantity can hold, like `m^200`.
+    PowerOutOfRange(String),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Number {
     Float(f32),
-    Int(i32),
+    Int(i128),
+    Quantity(Quantity),
+    Money(Money),
+    /// Seconds since 1970-01-01 UTC.
+    Date(i64),
+    /// Seconds.
+    Duration(i64),
+    /// Several results from one function, such as `linreg`.
+    List(Vec<Number>),
+}
+
+/// Variables stored with `=name`, kept between evaluations.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Env {
+    pub vars: BTreeMap<String, Number>,
+    /// The second operand of the most recent operation, like LASTX on HP
+    /// calculators.
+    pub lastx: Option<Number>,
+    /// The row `$n` and `$name` refer to when evaluating a table.
+    pub row: Row,
+    pub rates: Rates,
+    /// The time `now` and `today` give, in seconds since the epoch, or the
+    /// system clock when `None`.
+    pub clock: Option<i64>,
+    pub stats: Stats,
+}
+
+/// One line of a table along with the header names, if it had any.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Row {
+    pub names: Vec<String>,
+    pub values: Vec<String>,
 }
 
+impl Row {
+    pub fn get(&self, field: &Field) -> Option<&str> {
+        let index = match field {
+            Field::Index(n) => n.checked_sub(1)?,
+            Field::Name(name) => self.names.iter().position(|n| n == name)?,
+        };
+        self.values.get(index).map(String::as_str)
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub enum InterpretError {
     EvalError(EvalError),
     ParseError(ParserError),
 }
 
+impl EvalError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            EvalError::UnexpectedLiteral(_) => "E0009",
+            EvalError::UnboundSymbol(_) => "E0015",
+            EvalError::NothingToRecall => "E0016",
+            EvalError::MissingField(_) => "E0018",
+            EvalError::NotANumber(_, _) => "E0019",
+            EvalError::FreeVariable(_, _) => "E0026",
+            EvalError::NoConvergence(_) => "E0027",
+            EvalError::RangeBound(_, _) => "E0028",
+            EvalError::DimensionMismatch(_, _) => "E0029",
+            EvalError::UnitsNotAllowed(_, _) => "E0030",
+            EvalError::Money(e) => e.code(),
+            EvalError::TimeNotAllowed(_, _) => "E0036",
+            EvalError::TimeOutOfRange => "E0037",
+            EvalError::NotEnoughSamples(_, _) => "E0038",
+            EvalError::ListNotAllowed(_) => "E0039",
+            EvalError::NotAnInteger(_, _) => "E0040",
+            EvalError::OutOfDomain(_, _) => "E0041",
+            EvalError::NoInverse(_, _) => "E0042",
+            EvalError::IntegerOverflow(_) => "E0043",
+            EvalError::DivisionByZero => "E0044",
+            EvalError::TooManyTerms(_) => "E0046",
+            EvalError::PowerOutOfRange(_) => "E0047",
+        }
+    }
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalError::UnexpectedLiteral(token) => {
+                write!(f, "unexpected `{}` where a number was expected", token)
+            }
+            EvalError::UnboundSymbol(name) => write!(f, "`{}` is not defined", name),
+            EvalError::NothingToRecall => write!(f, "no operator has consumed a value yet"),
+            EvalError::MissingField(field) => write!(f, "the row has no field `{}`", field),
+            EvalError::NotANumber(field, text) => {
+                write!(f, "field `{}` is `{}`, which is not a number", field, text)
+            }
+            EvalError::FreeVariable(func, names) if names.is_empty() => {
+                write!(f, "`{}` needs a variable to vary in its quotation", func)
+            }
+            EvalError::FreeVariable(func, names) => write!(
+                f,
+                "`{}` needs exactly one undefined variable, found `{}`",
+                func,
+                names.join("`, `")
+            ),
+            EvalError::NoConvergence(steps) => {
+                write!(f, "no root found after {} iterations", steps)
+            }
+            EvalError::RangeBound(func, bound) => {
+                write!(f, "`{}` counts in whole numbers, not `{}`", func, bound)
+            }
+            EvalError::DimensionMismatch(x, y) => write!(
+                f,
+                "{} and {} measure different things",
+                describe(x),
+                describe(y)
+            ),
+            EvalError::UnitsNotAllowed(name, dimension) => {
+                write!(f, "`{}` can't take {}", name, describe(dimension))
+            }
+            EvalError::Money(e) => write!(f, "{}", e),
+            EvalError::TimeNotAllowed(name, operands) => {
+                write!(f, "`{}` can't take {}", name, operands)
+            }
+            EvalError::TimeOutOfRange => write!(f, "date or duration is out of range"),
+            EvalError::NotEnoughSamples(func, needed) => {
+                let (what, how) = match func {
+                    Func::Linreg | Func::Predict => ("x/y pair", "xy+"),
+                    _ => ("sample", "s+"),
+                };
+                let plural = if *needed == 1 { "" } else { "s" };
+                write!(
+                    f,
+                    "`{}` needs at least {} {}{}, add them with `{}`",
+                    func, needed, what, plural, how
+                )
+            }
+            EvalError::ListNotAllowed(name) => write!(f, "`{}` can't take a list", name),
+            EvalError::NotAnInteger(func, n) => {
+                write!(f, "`{}` takes whole numbers, not `{}`", func, n)
+            }
+            EvalError::OutOfDomain(func, n) => write!(f, "`{}` is not defined for `{}`", func, n),
+            EvalError::NoInverse(n, m) => write!(f, "`{}` has no inverse modulo `{}`", n, m),
+            EvalError::IntegerOverflow(name) => {
+                write!(f, "the result of `{}` is too large for an integer", name)
+            }
+            EvalError::DivisionByZero => write!(f, "integer division by zero"),
+            EvalError::TooManyTerms(func) => {
+                write!(f, "`{}` takes at most {} terms", func, MAX_TERMS)
+            }
+            EvalError::PowerOutOfRange(name) => write!(
+                f,
+                "the units of the result of `{}` have a power out of range",
+                name
+            ),
+        }
+    }
+}
+
+fn describe(dimension: &Dimension) -> String {
+    match *dimension == DIMENSIONLESS {
+        true => "a plain number".into(),
+        false => format!("`{}`", units::dimension_name(dimension)),
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+impl InterpretError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            InterpretError::EvalError(e) => e.code(),
+            InterpretError::ParseError(e) => e.code(),
+        }
+    }
+}
+
+impl Display for InterpretError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterpretError::EvalError(_) => write!(f, "could not evaluate expression"),
+            InterpretError::ParseError(_) => write!(f, "could not parse input"),
+        }
+    }
+}
+
+impl std::error::Error for InterpretError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            InterpretError::EvalError(e) => Some(e),
+            InterpretError::ParseError(e) => Some(e),
+        }
+    }
+}
+
 impl From<EvalError> for InterpretError {
     fn from(value: EvalError) -> Self {
         InterpretError::EvalError(value)
@@ -31,56 +259,230 @@ impl From<ParserError> for InterpretError {
     }
 }
 
+/// An integer result, or an error when it overflowed.
+fn integer(result: Option<i128>, name: impl Display) -> Result<Number, EvalError> {
+    result
+        .map(Number::Int)
+        .ok_or_else(|| EvalError::IntegerOverflow(name.to_string()))
+}
+
+/// Integers stay exact, failing rather than rounding when they overflow.
+/// Units are carried through by `Quantity`.
 impl Number {
-    pub fn add(&self, other: &Number) -> Number {
+    fn is_list(&self) -> bool {
+        matches!(self, Number::List(_))
+    }
+
+    fn is_time(&self) -> bool {
+        matches!(self, Number::Date(_) | Number::Duration(_))
+    }
+
+    pub fn add(&self, other: &Number) -> Result<Number, EvalError> {
+        match (self, other) {
+            (Number::Int(x), Number::Int(y)) => integer(x.checked_add(*y), Op::Add),
+            (x, y) if x.is_list() || y.is_list() => {
+                Err(EvalError::ListNotAllowed(Op::Add.to_string()))
+            }
+            (x, y) if x.is_time() || y.is_time() => dates::calc(Op::Add, self, other),
+            (Number::Money(_), _) | (_, Number::Money(_)) => money::calc(Op::Add, self, other),
+            (Number::Quantity(_), _) | (_, Number::Quantity(_)) => {
+                Ok(self.quantity().add(&other.quantity())?.into())
+            }
+            (x, y) => Ok(Number::Float(x.to_f32() + y.to_f32())),
+        }
+    }
+
+    pub fn sub(&self, other: &Number) -> Result<Number, EvalError> {
+        match (self, other) {
+            (Number::Int(x), Number::Int(y)) => integer(x.checked_sub(*y), Op::Sub),
+            (x, y) if x.is_list() || y.is_list() => {
+                Err(EvalError::ListNotAllowed(Op::Sub.to_string()))
+            }
+            (x, y) if x.is_time() || y.is_time() => dates::calc(Op::Sub, self, other),
+            (Number::Money(_), _) | (_, Number::Money(_)) => money::calc(Op::Sub, self, other),
+            (Number::Quantity(_), _) | (_, Number::Quantity(_)) => {
+                Ok(self.quantity().sub(&other.quantity())?.into())
+            }
+            (x, y) => Ok(Number::Float(x.to_f32() - y.to_f32())),
+        }
+    }
+
+    pub fn div(&self, other: &Number) -> Result<Number, EvalError> {
+        match (self, other) {
+            (Number::Int(_), Number::Int(0)) => Err(EvalError::DivisionByZero),
+            // Only the smallest integer divided by -1 overflows, and its
+            // negation is a power of two, which a float holds exactly.
+            (Number::Int(x), Number::Int(y)) => Ok(x
+                .checked_div(*y)
+                .map_or(Number::Float(-(*x as f32)), Number::Int)),
+            (x, y) if x.is_list() || y.is_list() => {
+                Err(EvalError::ListNotAllowed(Op::Div.to_string()))
+            }
+            (x, y) if x.is_time() || y.is_time() => dates::calc(Op::Div, self, other),
+            (Number::Money(_), _) | (_, Number::Money(_)) => money::calc(Op::Div, self, other),
+            (Number::Quantity(_), _) | (_, Number::Quantity(_)) => {
+                Ok(self.quantity().div(&other.quantity())?.into())
+            }
+            (x, y) => Ok(Number::Float(x.to_f32() / y.to_f32())),
+        }
+    }
+
+    pub fn mult(&self, other: &Number) -> Result<Number, EvalError> {
+        match (self, other) {
+            (Number::Int(x), Number::Int(y)) => integer(x.checked_mul(*y), Op::Mult),
+            (x, y) if x.is_list() || y.is_list() => {
+                Err(EvalError::ListNotAllowed(Op::Mult.to_string()))
+            }
+            (x, y) if x.is_time() || y.is_time() => dates::calc(Op::Mult, self, other),
+            (Number::Money(_), _) | (_, Number::Money(_)) => money::calc(Op::Mult, self, other),
+            (Number::Quantity(_), _) | (_, Number::Quantity(_)) => {
+                Ok(self.quantity().mult(&other.quantity())?.into())
+            }
+            (x, y) => Ok(Number::Float(x.to_f32() * y.to_f32())),
+        }
+    }
+
+    /// Stays an integer for non-negative integer exponents. A quantity can
+    /// only be raised to a whole power.
+    pub fn pow(&self, other: &Number) -> Result<Number, EvalError> {
+        match (self, other) {
+            (Number::Int(x), Number::Int(y)) if *y < 0 => {
+                Ok(Number::Float((*x as f32).powf(*y as f32)))
+            }
+            // Only 0, 1 and -1 can be raised past what a `u32` holds.
+            (Number::Int(x @ -1..=1), Number::Int(y)) if u32::try_from(*y).is_err() => {
+                Ok(Number::Int(if y % 2 == 0 { x * x } else { *x }))
+            }
+            (Number::Int(x), Number::Int(y)) => integer(
+                u32::try_from(*y).ok().and_then(|y| x.checked_pow(y)),
+                Op::Pow,
+            ),
+            (x, y) if x.is_list() || y.is_list() => {
+                Err(EvalError::ListNotAllowed(Op::Pow.to_string()))
+            }
+            (x, y) if x.is_time() || y.is_time() => dates::calc(Op::Pow, self, other),
+            (Number::Money(_), _) | (_, Number::Money(_)) => money::calc(Op::Pow, self, other),
+            (_, Number::Quantity(y)) => {
+                Err(EvalError::UnitsNotAllowed(Op::Pow.to_string(), y.dimension))
+            }
+            (Number::Quantity(x), Number::Int(y)) => Ok(x.pow(*y)?.into()),
+            (Number::Quantity(x), Number::Float(y)) if y.fract() == 0.0 => {
+                Ok(x.pow(*y as i128)?.into())
+            }
+            (Number::Quantity(x), _) => {
+                Err(EvalError::UnitsNotAllowed(Op::Pow.to_string(), x.dimension))
+            }
+            (x, y) => Ok(Number::Float(x.to_f32().powf(y.to_f32()))),
+        }
+    }
+
+    pub fn neg(&self) -> Result<Number, EvalError> {
         match self {
-            Number::Int(x) => match other {
-                Number::Int(y) => Number::Int(x + y),
-                Number::Float(y) => Number::Float(*x as f32 + y),
-            },
-            Number::Float(x) => match other {
-                Number::Int(y) => Number::Float(x + *y as f32),
-                Number::Float(y) => Number::Float(x + y),
-            },
+            Number::Int(x) => integer(x.checked_neg(), Func::Neg),
+            Number::Float(x) => Ok(Number::Float(-x)),
+            Number::Quantity(x) => Ok(Number::Quantity(Quantity {
+                value: -x.value,
+                ..x.clone()
+            })),
+            Number::Money(x) => Ok(Number::Money(x.neg()?)),
+            Number::Duration(x) => x
+                .checked_neg()
+                .map(Number::Duration)
+                .ok_or(EvalError::TimeOutOfRange),
+            Number::Date(_) => Err(self.time_not_allowed(Func::Neg.to_string())),
+            Number::List(_) => Err(EvalError::ListNotAllowed(Func::Neg.to_string())),
         }
     }
 
-    pub fn sub(&self, other: &Number) -> Number {
+    pub fn sqrt(&self) -> Result<Number, EvalError> {
         match self {
-            Number::Int(x) => match other {
-                Number::Int(y) => Number::Int(x - y),
-                Number::Float(y) => Number::Float(*x as f32 - y),
-            },
-            Number::Float(x) => match other {
-                Number::Int(y) => Number::Float(x - *y as f32),
-                Number::Float(y) => Number::Float(x - y),
-            },
+            Number::Quantity(x) => Ok(x.sqrt()?.into()),
+            Number::Money(_) => Err(MoneyError::NotAllowed(Func::Sqrt.to_string()).into()),
+            x if x.is_time() => Err(x.time_not_allowed(Func::Sqrt.to_string())),
+            Number::List(_) => Err(EvalError::ListNotAllowed(Func::Sqrt.to_string())),
+            x => Ok(Number::Float(x.to_f32().sqrt())),
         }
     }
 
-    pub fn div(&self, other: &Number) -> Number {
+    /// Applies a function that only makes sense on plain floats.
+    pub fn float(&self, func: Func, f: fn(f32) -> f32) -> Result<Number, EvalError> {
         match self {
-            Number::Int(x) => match other {
-                Number::Int(y) => Number::Int(x / y),
-                Number::Float(y) => Number::Float(*x as f32 / y),
-            },
-            Number::Float(x) => match other {
-                Number::Int(y) => Number::Float(x / *y as f32),
-                Number::Float(y) => Number::Float(x / y),
-            },
+            Number::Quantity(x) => Err(EvalError::UnitsNotAllowed(func.to_string(), x.dimension)),
+            Number::Money(_) => Err(MoneyError::NotAllowed(func.to_string()).into()),
+            x if x.is_time() => Err(x.time_not_allowed(func.to_string())),
+            Number::List(_) => Err(EvalError::ListNotAllowed(func.to_string())),
+            x => Ok(Number::Float(f(x.to_f32()))),
         }
     }
 
-    pub fn mult(&self, other: &Number) -> Number {
+    /// Shows the value in `unit`, which must measure the same thing.
+    pub fn convert(&self, unit: Unit) -> Result<Number, EvalError> {
         match self {
-            Number::Int(x) => match other {
-                Number::Int(y) => Number::Int(x * y),
-                Number::Float(y) => Number::Float(*x as f32 * y),
-            },
-            Number::Float(x) => match other {
-                Number::Int(y) => Number::Float(x * *y as f32),
-                Number::Float(y) => Number::Float(x * y),
-            },
+            Number::Money(_) => Err(MoneyError::NotAllowed(Func::Convert(unit).to_string()).into()),
+            x if x.is_time() => Err(x.time_not_allowed(Func::Convert(unit).to_string())),
+            Number::List(_) => Err(EvalError::ListNotAllowed(Func::Convert(unit).to_string())),
+            x => Ok(Number::Quantity(x.quantity().convert(unit)?)),
+        }
+    }
+
+    /// Seconds since the epoch of a date.
+    pub fn unix(&self) -> Result<Number, EvalError> {
+        match self {
+            Number::Date(x) => Ok(Number::Int((*x).into())),
+            x => Err(x.time_not_allowed(Func::Unix.to_string())),
+        }
+    }
+
+    /// The date a number of seconds since the epoch is.
+    pub fn iso(&self) -> Result<Number, EvalError> {
+        match self {
+            Number::Int(x) => i64::try_from(*x)
+                .map(Number::Date)
+                .map_err(|_| EvalError::TimeOutOfRange),
+            Number::Float(x) if x.is_finite() => Ok(Number::Date(x.round() as i64)),
+            Number::Float(_) => Err(EvalError::TimeOutOfRange),
+            x => Err(x.time_not_allowed(Func::Iso.to_string())),
+        }
+    }
+
+    /// The value of a plain number, or why `func` can't take this one.
+    pub fn plain(&self, func: Func) -> Result<f32, EvalError> {
+        self.float(func, |x| x).map(|x| x.to_f32())
+    }
+
+    fn time_not_allowed(&self, name: String) -> EvalError {
+        match self {
+            Number::List(_) => EvalError::ListNotAllowed(name),
+            n => EvalError::TimeNotAllowed(name, dates::describe(n)),
+        }
+    }
+
+    fn quantity(&self) -> Quantity {
+        match self {
+            Number::Quantity(x) => x.clone(),
+            x => Quantity::plain(x.to_f32()),
+        }
+    }
+
+    /// Quantities are in SI base units.
+    fn to_f32(&self) -> f32 {
+        match self {
+            Number::Int(n) => *n as f32,
+            Number::Float(n) => *n,
+            Number::Quantity(x) => x.value,
+            Number::Money(x) => x.amount.to_f32(),
+            Number::Date(x) | Number::Duration(x) => *x as f32,
+            Number::List(_) => f32::NAN,
+        }
+    }
+}
+
+/// A quantity whose units cancel out is a plain number again.
+impl From<Quantity> for Number {
+    fn from(value: Quantity) -> Self {
+        match value.dimension == DIMENSIONLESS {
+            true => Number::Float(value.value),
+            false => Number::Quantity(value),
         }
     }
 }
@@ -90,158 +492,665 @@ impl Display for Number {
         match self {
             Number::Float(n) => write!(f, "{}", n),
             Number::Int(n) => write!(f, "{}", n),
+            Number::Quantity(x) => write!(f, "{} {}", x.shown(), x.unit_name()),
+            Number::Money(x) => write!(f, "{}", x),
+            Number::Date(x) => write!(f, "{}", dates::iso_datetime(*x)),
+            Number::Duration(x) => write!(f, "{}", dates::duration_string(*x)),
+            Number::List(items) => {
+                write!(f, "[")?;
+                for item in items {
+                    write!(f, " {}", item)?;
+                }
+                write!(f, " ]")
+            }
+        }
+    }
+}
+
+pub fn calc(op: Op, x: &Number, y: &Number) -> Result<Number, EvalError> {
+    match op {
+        Op::Add => x.add(y),
+        Op::Sub => x.sub(y),
+        Op::Div => x.div(y),
+        Op::Mult => x.mult(y),
+        Op::Pow => x.pow(y),
+    }
+}
+
+/// Applies `func` to as many arguments as its arity.
+pub fn call(func: Func, args: &[Number]) -> Result<Number, EvalError> {
+    match (func, args) {
+        (Func::Neg, [x]) => x.neg(),
+        (Func::Sqrt, [x]) => x.sqrt(),
+        (Func::Sin, [x]) => x.float(func, f32::sin),
+        (Func::Cos, [x]) => x.float(func, f32::cos),
+        (Func::Tan, [x]) => x.float(func, f32::tan),
+        (Func::Ln, [x]) => x.float(func, f32::ln),
+        (Func::Exp, [x]) => x.float(func, f32::exp),
+        // `5 km` is five times a kilometre, and `2 kg m` a kilogram metre.
+        (Func::Unit(unit), [x]) => x.mult(&Number::Quantity(Quantity::new(1.0, unit))),
+        (Func::Convert(unit), [x]) => x.convert(unit),
+        (Func::Currency(currency), [x]) => Ok(Number::Money(Money::new(x, currency)?)),
+        (Func::Unix, [x]) => x.unix(),
+        (Func::Iso, [x]) => x.iso(),
+        (Func::Factorial, [n]) => integer::factorial(n),
+        (Func::Ncr, [n, k]) => integer::ncr(n, k),
+        (Func::Npr, [n, k]) => integer::npr(n, k),
+        (Func::Gcd, [a, b]) => integer::gcd(a, b),
+        (Func::Lcm, [a, b]) => integer::lcm(a, b),
+        (Func::IsPrime, [n]) => integer::isprime(n),
+        (Func::Factor, [n]) => integer::factor(n),
+        (Func::ModPow, [b, e, m]) => integer::modpow(b, e, m),
+        (Func::ModInv, [a, m]) => integer::modinv(a, m),
+        (Func::Exchange(_), _) => unreachable!("exchanging needs the rates, see `eval_node`"),
+        (Func::Now | Func::Today, _) => unreachable!("the clock is in the env, see `eval_node`"),
+        (func, _) if func.uses_stats() => {
+            unreachable!("the register is in the env, see `eval_node`")
         }
+        _ => unreachable!("the parser checks arity"),
     }
 }
 
-pub fn eval(expr: &Expr) -> Result<Number, EvalError> {
+pub fn eval(expr: &Expr, env: &mut Env) -> Result<Number, EvalError> {
+    eval_traced(expr, env, &mut vec![])
+}
+
+/// Like `eval`, also recording the value of every node as it is worked out,
+/// children before their parent.
+pub fn eval_traced(
+    expr: &Expr,
+    env: &mut Env,
+    trace: &mut Vec<Number>,
+) -> Result<Number, EvalError> {
+    let val = eval_node(expr, env, trace)?;
+    trace.push(val.clone());
+    Ok(val)
+}
+
+fn eval_node(expr: &Expr, env: &mut Env, trace: &mut Vec<Number>) -> Result<Number, EvalError> {
     match expr {
         Expr::Literal(Token::Int(n)) => Ok(Number::Int(*n)),
         Expr::Literal(Token::Float(n)) => Ok(Number::Float(*n)),
+        Expr::Literal(Token::Date(n)) => Ok(Number::Date(*n)),
+        Expr::Literal(Token::Duration(n)) => Ok(Number::Duration(*n)),
+        Expr::Literal(Token::Symbol(name)) => env
+            .vars
+            .get(name)
+            .cloned()
+            .ok_or_else(|| EvalError::UnboundSymbol(name.clone())),
+        Expr::Literal(Token::LastX) => env.lastx.clone().ok_or(EvalError::NothingToRecall),
+        Expr::Literal(Token::Field(field)) => {
+            let text = env
+                .row
+                .get(field)
+                .ok_or_else(|| EvalError::MissingField(field.clone()))?;
+            match Token::try_from(text.trim()) {
+                Ok(Token::Int(n)) => Ok(Number::Int(n)),
+                Ok(Token::Float(n)) => Ok(Number::Float(n)),
+                Ok(Token::Date(n)) => Ok(Number::Date(n)),
+                Ok(Token::Duration(n)) => Ok(Number::Duration(n)),
+                _ => Err(EvalError::NotANumber(field.clone(), text.into())),
+            }
+        }
+        Expr::Store(name, expr) => {
+            let val = eval_traced(expr, env, trace)?;
+            env.vars.insert(name.clone(), val.clone());
+            Ok(val)
+        }
         Expr::Calc(op, expr1, expr2) => {
-            let val1 = eval(expr1)?;
-            let val2 = eval(expr2)?;
+            let val1 = eval_traced(expr1, env, trace)?;
+            let val2 = eval_traced(expr2, env, trace)?;
 
-            match op {
-                Op::Add => Ok(val1.add(&val2)),
-                Op::Sub => Ok(val1.sub(&val2)),
-                Op::Div => Ok(val1.div(&val2)),
-                Op::Mult => Ok(val1.mult(&val2)),
-            }
+            let result = calc(*op, &val1, &val2)?;
+            env.lastx = Some(val2);
+            Ok(result)
+        }
+        Expr::Call(Func::Solve, args) => solve(args, env, trace),
+        Expr::Call(Func::Integrate, args) => integrate(args, env, trace),
+        Expr::Call(func @ (Func::Sum | Func::Product), args) => series(*func, args, env, trace),
+        Expr::Call(Func::Exchange(to), args) => {
+            let val = eval_traced(&args[0], env, trace)?;
+            let result = env.rates.exchange(&val, *to)?;
+            env.lastx = Some(val);
+            Ok(result)
+        }
+        Expr::Call(Func::Now, _) => Ok(Number::Date(env.clock.unwrap_or_else(dates::now))),
+        Expr::Call(Func::Today, _) => {
+            let now = env.clock.unwrap_or_else(dates::now);
+            Ok(Number::Date(now - now.rem_euclid(dates::SECONDS_PER_DAY)))
+        }
+        Expr::Call(func, args) => {
+            let args = args
+                .iter()
+                .map(|arg| eval_traced(arg, env, trace))
+                .collect::<Result<Vec<_>, _>>()?;
+            let result = match func.uses_stats() {
+                true => stats::call(*func, &args, &mut env.stats)?,
+                false => call(*func, &args)?,
+            };
+            env.lastx = args.last().cloned();
+            Ok(result)
         }
-        Expr::Literal(token) => Err(EvalError::UnexpectedLiteral(*token)),
+        Expr::Literal(token) => Err(EvalError::UnexpectedLiteral(token.clone())),
     }
 }
 
+/// The expression inside a quotation the parser has already checked.
+fn quoted(expr: &Expr) -> Vec<Expr> {
+    match expr {
+        Expr::Literal(Token::Quote(body)) => {
+            parser::quoted(body).expect("the parser checks quotations")
+        }
+        _ => unreachable!("the parser checks quotations"),
+    }
+}
+
+fn symbols(expr: &Expr, names: &mut BTreeSet<String>) {
+    match expr {
+        Expr::Literal(Token::Symbol(name)) => {
+            names.insert(name.clone());
+        }
+        Expr::Literal(_) => {}
+        Expr::Store(_, value) => symbols(value, names),
+        Expr::Calc(_, left, right) => {
+            symbols(left, names);
+            symbols(right, names);
+        }
+        Expr::Call(_, args) => args.iter().for_each(|arg| symbols(arg, names)),
+    }
+}
+
+/// The variable a quotation is a function of: the one that isn't defined,
+/// or its only variable if that is.
+fn free_variable(func: Func, body: &Expr, env: &Env) -> Result<String, EvalError> {
+    let mut names = BTreeSet::new();
+    symbols(body, &mut names);
+    let free: Vec<String> = names
+        .iter()
+        .filter(|name| !env.vars.contains_key(*name))
+        .cloned()
+        .collect();
+    match (free.as_slice(), names.len()) {
+        ([name], _) => Ok(name.clone()),
+        ([], 1) => Ok(names.into_iter().next().unwrap()),
+        _ => Err(EvalError::FreeVariable(func, free)),
+    }
+}
+
+/// `expr` as a function of `var`, evaluated in a copy of `env` so nothing
+/// it stores is kept.
+fn function_of<'a>(
+    expr: &'a Expr,
+    var: &'a str,
+    env: &Env,
+) -> impl FnMut(f32) -> Result<f32, EvalError> + 'a {
+    let mut scope = env.clone();
+    move |x| {
+        scope.vars.insert(var.into(), Number::Float(x));
+        Ok(eval(expr, &mut scope)?.to_f32())
+    }
+}
+
+fn solve(args: &[Expr], env: &mut Env, trace: &mut Vec<Number>) -> Result<Number, EvalError> {
+    let body = quoted(&args[0]).remove(0);
+    let var = free_variable(Func::Solve, &body, env)?;
+    let start = match &args[1] {
+        interval @ Expr::Literal(Token::Quote(_)) => {
+            let bounds = quoted(interval);
+            Start::Interval(
+                eval(&bounds[0], env)?.to_f32(),
+                eval(&bounds[1], env)?.to_f32(),
+            )
+        }
+        guess => Start::Guess(eval_traced(guess, env, trace)?.to_f32()),
+    };
+    let slope = symbolic::derivative(&body, &var);
+    let root = numeric::solve(
+        function_of(&body, &var, env),
+        function_of(&slope, &var, env),
+        start,
+    )?;
+    Ok(Number::Float(root))
+}
+
+fn integrate(args: &[Expr], env: &mut Env, trace: &mut Vec<Number>) -> Result<Number, EvalError> {
+    let body = quoted(&args[0]).remove(0);
+    let var = free_variable(Func::Integrate, &body, env)?;
+    let low = eval_traced(&args[1], env, trace)?.to_f32();
+    let high = eval_traced(&args[2], env, trace)?.to_f32();
+    let (value, error) = numeric::integrate(function_of(&body, &var, env), low, high)?;
+    // Like the HP 48, the error estimate is left in `ierr`.
+    env.vars.insert(IERR.into(), Number::Float(error));
+    Ok(Number::Float(value))
+}
+
+/// The error estimate of the last `integrate`.
+const IERR: &str = "ierr";
+/// How many terms `sum` and `product` will work out before giving up.
+const MAX_TERMS: i128 = 1_000_000;
+
+fn whole(func: Func, bound: Number) -> Result<i128, EvalError> {
+    match bound {
+        Number::Int(n) => Ok(n),
+        Number::Float(n) if n.fract() == 0.0 && n.is_finite() => Ok(n as i128),
+        bound => Err(EvalError::RangeBound(func, bound)),
+    }
+}
+
+/// `sum` or `product`, with the quotation's variable bound to each whole
+/// number from the low bound to the high one. An empty range gives `0` or `1`.
+fn series(
+    func: Func,
+    args: &[Expr],
+    env: &mut Env,
+    trace: &mut Vec<Number>,
+) -> Result<Number, EvalError> {
+    let body = quoted(&args[0]).remove(0);
+    let var = free_variable(func, &body, env)?;
+    let low = whole(func, eval_traced(&args[1], env, trace)?)?;
+    let high = whole(func, eval_traced(&args[2], env, trace)?)?;
+    if high.saturating_sub(low) >= MAX_TERMS {
+        return Err(EvalError::TooManyTerms(func));
+    }
+    let mut scope = env.clone();
+    let mut total = match func {
+        Func::Sum => Number::Int(0),
+        _ => Number::Int(1),
+    };
+    for n in low..=high {
+        scope.vars.insert(var.clone(), Number::Int(n));
+        let term = eval(&body, &mut scope)?;
+        total = match func {
+            Func::Sum => total.add(&term)?,
+            _ => total.mult(&term)?,
+        };
+    }
+    Ok(total)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::tokenizer::TokenParseError;
+
+    #[test]
+    fn test_error_display() {
+        let err = InterpretError::from(ParserError::UnrecognizedToken(
+            "3-2".into(),
+            TokenParseError::GluedOperator,
+        ));
+        assert_eq!(err.code(), "E0005");
+        assert_eq!(err.to_string(), "could not parse input");
+
+        let err = InterpretError::from(EvalError::UnexpectedLiteral(Token::Operation(Op::Add)));
+        assert_eq!(err.code(), "E0009");
+        assert_eq!(
+            std::error::Error::source(&err).unwrap().to_string(),
+            "unexpected `+` where a number was expected"
+        );
+    }
+
+    #[test]
+    fn test_eval_env() {
+        let mut env = Env::default();
+        assert_eq!(
+            eval(&Expr::Literal(Token::Symbol("x".into())), &mut env),
+            Err(EvalError::UnboundSymbol("x".into()))
+        );
+        assert_eq!(
+            eval(
+                &Expr::Store(
+                    "x".into(),
+                    Box::new(Expr::Calc(
+                        Op::Add,
+                        Box::new(Expr::Literal(Token::Int(3))),
+                        Box::new(Expr::Literal(Token::Int(4)))
+                    ))
+                ),
+                &mut env
+            ),
+            Ok(Number::Int(7))
+        );
+        assert_eq!(
+            eval(
+                &Expr::Calc(
+                    Op::Mult,
+                    Box::new(Expr::Literal(Token::Symbol("x".into()))),
+                    Box::new(Expr::Literal(Token::Int(2)))
+                ),
+                &mut env
+            ),
+            Ok(Number::Int(14))
+        );
+        assert_eq!(env.lastx, Some(Number::Int(2)));
+        assert_eq!(
+            eval(&Expr::Literal(Token::LastX), &mut env),
+            Ok(Number::Int(2))
+        );
+        assert_eq!(
+            eval(&Expr::Literal(Token::LastX), &mut Env::default()),
+            Err(EvalError::NothingToRecall)
+        );
+    }
+
+    #[test]
+    fn test_eval_row() {
+        let mut env = Env {
+            row: Row {
+                names: vec!["qty".into(), "price".into()],
+                values: vec!["3".into(), " 2.5 ".into(), "n/a".into()],
+            },
+            ..Env::default()
+        };
+        assert_eq!(
+            eval(
+                &Expr::Calc(
+                    Op::Mult,
+                    Box::new(Expr::Literal(Token::Field(Field::Name("qty".into())))),
+                    Box::new(Expr::Literal(Token::Field(Field::Index(2))))
+                ),
+                &mut env
+            ),
+            Ok(Number::Float(7.5))
+        );
+        assert_eq!(
+            eval(&Expr::Literal(Token::Field(Field::Index(3))), &mut env),
+            Err(EvalError::NotANumber(Field::Index(3), "n/a".into()))
+        );
+        assert_eq!(
+            eval(&Expr::Literal(Token::Field(Field::Index(4))), &mut env),
+            Err(EvalError::MissingField(Field::Index(4)))
+        );
+        assert_eq!(
+            eval(
+                &Expr::Literal(Token::Field(Field::Name("total".into()))),
+                &mut env
+            ),
+            Err(EvalError::MissingField(Field::Name("total".into())))
+        );
+    }
+
+    #[test]
+    fn test_eval_functions() {
+        let int = |n| Box::new(Expr::Literal(Token::Int(n)));
+        let mut env = Env::default();
+        assert_eq!(
+            eval(&Expr::Calc(Op::Pow, int(2), int(10)), &mut env),
+            Ok(Number::Int(1024))
+        );
+        assert_eq!(
+            eval(&Expr::Calc(Op::Pow, int(2), int(-1)), &mut env),
+            Ok(Number::Float(0.5))
+        );
+        assert_eq!(
+            eval(&Expr::Calc(Op::Pow, int(10), int(10)), &mut env),
+            Ok(Number::Int(10000000000))
+        );
+        assert_eq!(
+            eval(&Expr::Calc(Op::Add, int(i32::MAX.into()), int(1)), &mut env),
+            Ok(Number::Int(2147483648))
+        );
+        assert_eq!(
+            eval(&Expr::Calc(Op::Mult, int(i128::MAX), int(2)), &mut env),
+            Err(EvalError::IntegerOverflow("*".into()))
+        );
+        assert_eq!(
+            eval(&Expr::Calc(Op::Div, int(7), int(-2)), &mut env),
+            Ok(Number::Int(-3))
+        );
+        assert_eq!(
+            eval(&Expr::Calc(Op::Div, int(1), int(0)), &mut env),
+            Err(EvalError::DivisionByZero)
+        );
+        assert_eq!(
+            eval(&Expr::Calc(Op::Div, int(i128::MIN), int(-1)), &mut env),
+            Ok(Number::Float(2f32.powi(127)))
+        );
+        assert_eq!(
+            eval(&Expr::Calc(Op::Pow, int(2), int(127)), &mut env),
+            Err(EvalError::IntegerOverflow("^".into()))
+        );
+        assert_eq!(
+            eval(&Expr::Calc(Op::Pow, int(-1), int(5000000001)), &mut env),
+            Ok(Number::Int(-1))
+        );
+        assert_eq!(
+            eval(&Expr::Call(Func::Sqrt, vec![*int(16)]), &mut env),
+            Ok(Number::Float(4.0))
+        );
+        assert_eq!(env.lastx, Some(Number::Int(16)));
+        assert_eq!(
+            eval(&Expr::Call(Func::Neg, vec![*int(3)]), &mut env),
+            Ok(Number::Int(-3))
+        );
+        assert_eq!(
+            eval(
+                &Expr::Call(Func::Neg, vec![*int(i32::MIN.into())]),
+                &mut env
+            ),
+            Ok(Number::Int(2147483648))
+        );
+        assert_eq!(
+            eval(&Expr::Call(Func::Neg, vec![*int(i128::MIN)]), &mut env),
+            Err(EvalError::IntegerOverflow("neg".into()))
+        );
+        assert_eq!(
+            eval(&Expr::Call(Func::Exp, vec![*int(0)]), &mut env),
+            Ok(Number::Float(1.0))
+        );
+        assert_eq!(
+            eval(&Expr::Call(Func::Cos, vec![*int(0)]), &mut env),
+            Ok(Number::Float(1.0))
+        );
+    }
+
+    #[test]
+    fn test_eval_traced() {
+        let mut trace = vec![];
+        let expr = Expr::Store(
+            "x".into(),
+            Box::new(Expr::Calc(
+                Op::Sub,
+                Box::new(Expr::Literal(Token::Int(3))),
+                Box::new(Expr::Call(Func::Neg, vec![Expr::Literal(Token::Int(2))])),
+            )),
+        );
+        assert_eq!(
+            eval_traced(&expr, &mut Env::default(), &mut trace),
+            Ok(Number::Int(5))
+        );
+        assert_eq!(
+            trace,
+            vec![
+                Number::Int(3),
+                Number::Int(2),
+                Number::Int(-2),
+                Number::Int(5),
+                Number::Int(5)
+            ]
+        );
+
+        trace.clear();
+        let expr = Expr::Calc(
+            Op::Add,
+            Box::new(Expr::Literal(Token::Int(1))),
+            Box::new(Expr::Literal(Token::Symbol("y".into()))),
+        );
+        assert!(eval_traced(&expr, &mut Env::default(), &mut trace).is_err());
+        assert_eq!(trace, vec![Number::Int(1)]);
+    }
 
     #[test]
     fn test_eval() {
+        let mut env = Env::default();
         assert_eq!(
-            eval(&Expr::Literal(Token::Operation(Op::Add))),
+            eval(&Expr::Literal(Token::Operation(Op::Add)), &mut env),
             Err(EvalError::UnexpectedLiteral(Token::Operation(Op::Add)))
         );
         assert_eq!(
-            eval(&Expr::Literal(Token::Operation(Op::Sub))),
+            eval(&Expr::Literal(Token::Operation(Op::Sub)), &mut env),
             Err(EvalError::UnexpectedLiteral(Token::Operation(Op::Sub)))
         );
         assert_eq!(
-            eval(&Expr::Literal(Token::Operation(Op::Div))),
+            eval(&Expr::Literal(Token::Operation(Op::Div)), &mut env),
             Err(EvalError::UnexpectedLiteral(Token::Operation(Op::Div)))
         );
         assert_eq!(
-            eval(&Expr::Literal(Token::Operation(Op::Mult))),
+            eval(&Expr::Literal(Token::Operation(Op::Mult)), &mut env),
             Err(EvalError::UnexpectedLiteral(Token::Operation(Op::Mult)))
         );
         assert_eq!(
-            eval(&Expr::Literal(Token::Unrecognized)),
-            Err(EvalError::UnexpectedLiteral(Token::Unrecognized)),
+            eval(
+                &Expr::Literal(Token::Unrecognized(
+                    "f".into(),
+                    TokenParseError::UnknownWord
+                )),
+                &mut env
+            ),
+            Err(EvalError::UnexpectedLiteral(Token::Unrecognized(
+                "f".into(),
+                TokenParseError::UnknownWord
+            ))),
         );
 
-        assert_eq!(eval(&Expr::Literal(Token::Int(1))), Ok(Number::Int(1)));
         assert_eq!(
-            eval(&Expr::Literal(Token::Float(1.2))),
+            eval(&Expr::Literal(Token::Int(1)), &mut env),
+            Ok(Number::Int(1))
+        );
+        assert_eq!(
+            eval(&Expr::Literal(Token::Float(1.2)), &mut env),
             Ok(Number::Float(1.2))
         );
 
         assert_eq!(
-            eval(&Expr::Calc(
-                Op::Add,
-                Box::new(Expr::Literal(Token::Int(1))),
-                Box::new(Expr::Literal(Token::Int(1))),
-            )),
+            eval(
+                &Expr::Calc(
+                    Op::Add,
+                    Box::new(Expr::Literal(Token::Int(1))),
+                    Box::new(Expr::Literal(Token::Int(1))),
+                ),
+                &mut env
+            ),
             Ok(Number::Int(2))
         );
         assert_eq!(
-            eval(&Expr::Calc(
-                Op::Add,
-                Box::new(Expr::Literal(Token::Int(-1))),
-                Box::new(Expr::Literal(Token::Int(-11))),
-            )),
+            eval(
+                &Expr::Calc(
+                    Op::Add,
+                    Box::new(Expr::Literal(Token::Int(-1))),
+                    Box::new(Expr::Literal(Token::Int(-11))),
+                ),
+                &mut env
+            ),
             Ok(Number::Int(-12))
         );
         assert_eq!(
-            eval(&Expr::Calc(
-                Op::Add,
-                Box::new(Expr::Literal(Token::Int(-1))),
-                Box::new(Expr::Literal(Token::Int(11))),
-            )),
+            eval(
+                &Expr::Calc(
+                    Op::Add,
+                    Box::new(Expr::Literal(Token::Int(-1))),
+                    Box::new(Expr::Literal(Token::Int(11))),
+                ),
+                &mut env
+            ),
             Ok(Number::Int(10))
         );
         assert_eq!(
-            eval(&Expr::Calc(
-                Op::Sub,
-                Box::new(Expr::Literal(Token::Int(1))),
-                Box::new(Expr::Literal(Token::Int(1))),
-            )),
+            eval(
+                &Expr::Calc(
+                    Op::Sub,
+                    Box::new(Expr::Literal(Token::Int(1))),
+                    Box::new(Expr::Literal(Token::Int(1))),
+                ),
+                &mut env
+            ),
             Ok(Number::Int(0))
         );
         assert_eq!(
-            eval(&Expr::Calc(
-                Op::Div,
-                Box::new(Expr::Literal(Token::Int(2))),
-                Box::new(Expr::Literal(Token::Int(1))),
-            )),
+            eval(
+                &Expr::Calc(
+                    Op::Div,
+                    Box::new(Expr::Literal(Token::Int(2))),
+                    Box::new(Expr::Literal(Token::Int(1))),
+                ),
+                &mut env
+            ),
             Ok(Number::Int(2))
         );
         assert_eq!(
-            eval(&Expr::Calc(
-                Op::Mult,
-                Box::new(Expr::Literal(Token::Int(2))),
-                Box::new(Expr::Literal(Token::Int(3))),
-            )),
+            eval(
+                &Expr::Calc(
+                    Op::Mult,
+                    Box::new(Expr::Literal(Token::Int(2))),
+                    Box::new(Expr::Literal(Token::Int(3))),
+                ),
+                &mut env
+            ),
             Ok(Number::Int(6))
         );
 
         assert_eq!(
-            eval(&Expr::Calc(
-                Op::Add,
-                Box::new(Expr::Literal(Token::Float(1.4))),
-                Box::new(Expr::Literal(Token::Int(1))),
-            )),
+            eval(
+                &Expr::Calc(
+                    Op::Add,
+                    Box::new(Expr::Literal(Token::Float(1.4))),
+                    Box::new(Expr::Literal(Token::Int(1))),
+                ),
+                &mut env
+            ),
             Ok(Number::Float(2.4))
         );
         assert_eq!(
-            eval(&Expr::Calc(
-                Op::Add,
-                Box::new(Expr::Literal(Token::Int(1))),
-                Box::new(Expr::Literal(Token::Float(1.4))),
-            )),
+            eval(
+                &Expr::Calc(
+                    Op::Add,
+                    Box::new(Expr::Literal(Token::Int(1))),
+                    Box::new(Expr::Literal(Token::Float(1.4))),
+                ),
+                &mut env
+            ),
             Ok(Number::Float(2.4))
         );
         assert_eq!(
-            eval(&Expr::Calc(
-                Op::Add,
-                Box::new(Expr::Literal(Token::Float(1.4))),
-                Box::new(Expr::Literal(Token::Float(1.4))),
-            )),
+            eval(
+                &Expr::Calc(
+                    Op::Add,
+                    Box::new(Expr::Literal(Token::Float(1.4))),
+                    Box::new(Expr::Literal(Token::Float(1.4))),
+                ),
+                &mut env
+            ),
             Ok(Number::Float(2.8))
         );
 
         assert_eq!(
-            eval(&Expr::Calc(
-                Op::Add,
-                Box::new(Expr::Literal(Token::Float(1.4))),
-                Box::new(Expr::Calc(
-                    Op::Mult,
+            eval(
+                &Expr::Calc(
+                    Op::Add,
                     Box::new(Expr::Literal(Token::Float(1.4))),
-                    Box::new(Expr::Literal(Token::Int(2)))
-                )),
-            )),
+                    Box::new(Expr::Calc(
+                        Op::Mult,
+                        Box::new(Expr::Literal(Token::Float(1.4))),
+                        Box::new(Expr::Literal(Token::Int(2)))
+                    )),
+                ),
+                &mut env
+            ),
             Ok(Number::Float(4.2))
         );
         assert_eq!(
-            eval(&Expr::Calc(
-                Op::Add,
-                Box::new(Expr::Calc(
-                    Op::Mult,
+            eval(
+                &Expr::Calc(
+                    Op::Add,
+                    Box::new(Expr::Calc(
+                        Op::Mult,
+                        Box::new(Expr::Literal(Token::Float(1.4))),
+                        Box::new(Expr::Literal(Token::Int(1)))
+                    )),
                     Box::new(Expr::Literal(Token::Float(1.4))),
-                    Box::new(Expr::Literal(Token::Int(1)))
-                )),
-                Box::new(Expr::Literal(Token::Float(1.4))),
-            )),
+                ),
+                &mut env
+            ),
             Ok(Number::Float(2.8))
         );
     }
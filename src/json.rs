@@ -0,0 +1,209 @@
+use std::error::Error;
+use std::fmt::Display;
+
+use crate::interpreter::{InterpretError, Number};
+
+/// Just enough JSON to describe a result, written out by hand so the binary
+/// doesn't need a serialization library.
+#[derive(Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut std::fmt::Formatter<'_>, s: &str) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for ch in s.chars() {
+        match ch {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            ch if ch.is_control() => write!(f, "\\u{:04x}", ch as u32)?,
+            ch => write!(f, "{}", ch)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl From<&Number> for Json {
+    fn from(value: &Number) -> Self {
+        match value {
+            Number::Int(n) => Json::Number(n.to_string()),
+            // JSON has no way to write NaN or infinity.
+            Number::Float(n) if !n.is_finite() => Json::Null,
+            Number::Float(n) => Json::Number(n.to_string()),
+            Number::Quantity(x) => Json::Object(vec![
+                ("value", Json::from(&Number::Float(x.shown()))),
+                ("unit", Json::String(x.unit_name())),
+            ]),
+            // Written exactly, JSON numbers can have any number of digits.
+            Number::Money(x) => Json::Object(vec![
+                ("amount", Json::Number(x.amount.to_string())),
+                ("currency", Json::String(x.currency.to_string())),
+            ]),
+            Number::Date(_) | Number::Duration(_) => Json::String(value.to_string()),
+            Number::List(items) => Json::Array(items.iter().map(Json::from).collect()),
+        }
+    }
+}
+
+fn type_name(value: &Number) -> Json {
+    Json::String(
+        match value {
+            Number::Int(_) => "int",
+            Number::Float(_) => "float",
+            Number::Quantity(_) => "quantity",
+            Number::Money(_) => "money",
+            Number::Date(_) => "date",
+            Number::Duration(_) => "duration",
+            Number::List(_) => "list",
+        }
+        .into(),
+    )
+}
+
+impl From<&InterpretError> for Json {
+    fn from(value: &InterpretError) -> Self {
+        let (stage, error): (_, &dyn Error) = match value {
+            InterpretError::ParseError(e) => ("parse", e),
+            InterpretError::EvalError(e) => ("eval", e),
+        };
+        Json::Object(vec![
+            ("code", Json::String(value.code().into())),
+            ("stage", Json::String(stage.into())),
+            ("message", Json::String(error.to_string())),
+            (
+                "cause",
+                error
+                    .source()
+                    .map_or(Json::Null, |cause| Json::String(cause.to_string())),
+            ),
+        ])
+    }
+}
+
+/// Describes one line of input. A line with several expressions gets arrays
+/// for `result` and `type`.
+pub fn line(input: &str, result: &Result<Vec<Number>, InterpretError>) -> Json {
+    let (result, kind, error) = match result {
+        Ok(vals) if vals.len() == 1 => (Json::from(&vals[0]), type_name(&vals[0]), Json::Null),
+        Ok(vals) => (
+            Json::Array(vals.iter().map(Json::from).collect()),
+            Json::Array(vals.iter().map(type_name).collect()),
+            Json::Null,
+        ),
+        Err(e) => (Json::Null, Json::Null, Json::from(e)),
+    };
+    Json::Object(vec![
+        ("input", Json::String(input.into())),
+        ("result", result),
+        ("type", kind),
+        ("error", error),
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::EvalError;
+    use crate::parser::ParserError;
+    use crate::tokenizer::TokenParseError;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            Json::String("say \"hi\"\\\n\u{1}".into()).to_string(),
+            r#""say \"hi\"\\\n\u0001""#
+        );
+        assert_eq!(
+            Json::Object(vec![
+                ("a", Json::Array(vec![Json::Null, Json::Number("1".into())])),
+                ("b", Json::Object(vec![]))
+            ])
+            .to_string(),
+            r#"{"a":[null,1],"b":{}}"#
+        );
+    }
+
+    #[test]
+    fn test_line() {
+        assert_eq!(
+            line("3 4 +", &Ok(vec![Number::Int(7)])).to_string(),
+            r#"{"input":"3 4 +","result":7,"type":"int","error":null}"#
+        );
+        assert_eq!(
+            line("1 2 / ; 1.5", &Ok(vec![Number::Int(0), Number::Float(1.5)])).to_string(),
+            r#"{"input":"1 2 / ; 1.5","result":[0,1.5],"type":["int","float"],"error":null}"#
+        );
+        assert_eq!(
+            line("1 0. /", &Ok(vec![Number::Float(f32::INFINITY)])).to_string(),
+            r#"{"input":"1 0. /","result":null,"type":"float","error":null}"#
+        );
+        assert_eq!(
+            line(
+                "2024-04-07 ; 1d",
+                &Ok(vec![Number::Date(1712448000), Number::Duration(86400)])
+            )
+            .to_string(),
+            r#"{"input":"2024-04-07 ; 1d","result":["2024-04-07","1d"],"type":["date","duration"],"error":null}"#
+        );
+        assert_eq!(
+            line(
+                "3-2",
+                &Err(
+                    ParserError::UnrecognizedToken("3-2".into(), TokenParseError::GluedOperator)
+                        .into()
+                )
+            )
+            .to_string(),
+            concat!(
+                r#"{"input":"3-2","result":null,"type":null,"error":{"code":"E0005","#,
+                r#""stage":"parse","message":"unrecognized token `3-2`","#,
+                r#""cause":"operator attached to a number, separate them with a space"}}"#
+            )
+        );
+        assert_eq!(
+            line("x", &Err(EvalError::UnboundSymbol("x".into()).into())).to_string(),
+            concat!(
+                r#"{"input":"x","result":null,"type":null,"error":{"code":"E0015","#,
+                r#""stage":"eval","message":"`x` is not defined","cause":null}}"#
+            )
+        );
+    }
+}
@@ -1,62 +1,274 @@
-use atty;
+use cli::{AstFormat, Command, Input, Options, OutputFormat};
+use format::DisplayMode;
 use interpreter::{InterpretError, Number};
-use std::io::{self, BufRead, Write};
+use itertools::Itertools;
+use money::Rates;
+use parser::Expr;
+use session::{Session, Syntax};
+use std::error::Error;
+use std::io::{self, BufRead};
+use std::path::Path;
 
+mod ast;
+mod cli;
+mod commands;
+mod csv;
+mod dates;
+mod format;
+mod infix;
+mod integer;
 mod interpreter;
+mod json;
+mod money;
+mod numeric;
+mod optimizer;
 mod parser;
+mod printer;
+mod repl;
+mod session;
+mod stats;
+mod symbolic;
 mod tokenizer;
+mod units;
 
-fn prompt(input: &mut String) -> io::Result<usize> {
-    print!("$ ");
-    let _ = io::stdout().flush();
-    io::stdin().read_line(input)
+fn render(values: &[Number], format: OutputFormat, display: &DisplayMode) -> String {
+    let separator = match format {
+        OutputFormat::Lines | OutputFormat::Json => "\n",
+        OutputFormat::Inline => ", ",
+    };
+    values.iter().map(|val| display.format(val)).join(separator)
 }
 
-fn interpret(text: String) -> Result<Number, InterpretError> {
-    let tokens = tokenizer::tokenize(text);
-    let expr = parser::parse(tokens)?;
-    Ok(interpreter::eval(&expr)?)
+fn report(err: &InterpretError) -> String {
+    let mut message = format!("error[{}]: {}", err.code(), err);
+    let mut source = err.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    message
 }
 
-fn main() {
-    let mut input = String::new();
-
-    if !atty::is(atty::Stream::Stdin) {
-        for line in io::stdin().lock().lines() {
-            let text = match line {
-                Ok(text) => text,
-                Err(e) => {
-                    println!("Could not read line: {}", e);
-                    continue;
-                }
-            };
-            match interpret(text) {
-                Err(InterpretError::ParseError(e)) => {
-                    println!("Could not parse input: {:?}", e);
+/// The rates from `--rates`, exiting when they can't be read.
+fn load_rates(options: &Options) -> Rates {
+    let Some(path) = &options.rates else {
+        return Rates::default();
+    };
+    match Rates::load(path, dates::today()) {
+        Ok(rates) => rates,
+        Err(e) => {
+            eprintln!("rpn: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn new_session(options: &Options) -> Session {
+    let mut session = Session::default();
+    session.env.rates = load_rates(options);
+    session.display = options.display;
+    session.syntax = options.syntax;
+    session.optimize = options.optimize;
+    session
+}
+
+/// The line rewritten as RPN for `--show-rpn`. Lines that can't be
+/// translated are left for `Session::run` to report.
+fn show_rpn(syntax: Syntax, text: &str) -> Option<String> {
+    let tokens = syntax.translate(text.into()).ok()?;
+    Some(tokens.iter().join(" "))
+}
+
+fn render_ast(format: AstFormat, session: &Session) -> String {
+    match format {
+        AstFormat::Dot => ast::dot(&session.trace.program),
+        AstFormat::Tree => ast::tree(&session.trace, &session.display),
+        #[cfg(feature = "serde")]
+        AstFormat::Json => {
+            serde_json::to_string(&session.trace.program).expect("syntax trees always serialize")
+        }
+        #[cfg(not(feature = "serde"))]
+        AstFormat::Json => unreachable!("`--ast json` is rejected without serde"),
+    }
+}
+
+/// A line of input: text to evaluate, or a program from `--load-ast`.
+enum Line {
+    Text(String),
+    #[cfg_attr(not(feature = "serde"), allow(dead_code))]
+    Program(Vec<Expr>),
+}
+
+/// Skips blank lines, like the prompt does, keeping the numbers of the rest.
+fn text_lines<I: Iterator<Item = (usize, io::Result<String>)>>(
+    lines: I,
+) -> impl Iterator<Item = (usize, io::Result<Line>)> {
+    lines
+        .filter(|(_, line)| !matches!(line, Ok(text) if text.trim().is_empty()))
+        .map(|(index, line)| (index, line.map(Line::Text)))
+}
+
+/// Reads one JSON program per line, as written by `--dump-ast json`.
+#[cfg(feature = "serde")]
+fn program_lines(data: &str) -> impl Iterator<Item = (usize, io::Result<Line>)> + '_ {
+    data.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let program = serde_json::from_str(line).map_err(io::Error::from);
+            (index, program.map(Line::Program))
+        })
+}
+
+/// Prints the outcome of a line and returns whether it failed.
+fn report_line(
+    index: usize,
+    text: &str,
+    result: Result<Vec<Number>, InterpretError>,
+    session: &Session,
+    options: &Options,
+) -> bool {
+    let format = options.format.unwrap_or(OutputFormat::Lines);
+    if let (Some(ast), false) = (options.ast, session.trace.program.is_empty()) {
+        println!("{}", render_ast(ast, session));
+    }
+    match &result {
+        _ if format == OutputFormat::Json => println!("{}", json::line(text, &result)),
+        Ok(_) if options.ast.is_some() => {}
+        Ok(vals) if vals.is_empty() => {}
+        Ok(vals) => println!("{}", render(vals, format, &session.display)),
+        Err(e) => eprintln!("line {}: {}", index + 1, report(e)),
+    }
+    result.is_err()
+}
+
+/// Evaluates each line, printing results to stdout and failures to stderr,
+/// or both to stdout as JSON. Returns whether every line succeeded.
+fn run_lines<I: Iterator<Item = (usize, io::Result<Line>)>>(lines: I, options: &Options) -> bool {
+    let format = options.format.unwrap_or(OutputFormat::Lines);
+    let mut session = new_session(options);
+    let mut success = true;
+    for (index, line) in lines {
+        let failed = match line {
+            Ok(Line::Program(program)) => {
+                let text = program.iter().map(printer::rpn).join(" ; ");
+                if options.show_rpn && format != OutputFormat::Json {
+                    println!("{}", text);
                 }
-                Err(InterpretError::EvalError(e)) => {
-                    println!("Could not evaluate expression: {:?}", e);
+                let result = session.run_program(program);
+                report_line(index, &text, result, &session, options)
+            }
+            Ok(Line::Text(text)) => {
+                if options.show_rpn && format != OutputFormat::Json {
+                    if let Some(rpn) = show_rpn(session.syntax, &text) {
+                        println!("{}", rpn);
+                    }
                 }
-                Ok(val) => println!("{}", val),
+                let result = session.run(text.clone());
+                report_line(index, &text, result, &session, options)
+            }
+            Err(e) => {
+                eprintln!("line {}: could not read line: {}", index + 1, e);
+                true
             }
+        };
+        if failed {
+            success = false;
+            if options.fail_fast {
+                break;
+            }
+        }
+    }
+    success
+}
+
+fn read_file(path: &Path) -> String {
+    match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("rpn: could not read {}: {}", path.display(), e);
+            std::process::exit(1);
         }
-        return ();
     }
+}
 
-    println!("Reverse polish notation calculator");
-    let _ = io::stdout().flush();
-    while let Ok(_n) = prompt(&mut input) {
-        let result = interpret(input.clone());
-        input.clear();
-        match result {
-            Err(InterpretError::ParseError(e)) => {
-                println!("Could not parse input: {:?}", e);
-            }
-            Err(InterpretError::EvalError(e)) => {
-                println!("Could not evaluate expression: {:?}", e);
+fn main() {
+    let options = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        Ok(Command::Version) => {
+            println!("rpn {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(e) => {
+            eprintln!("rpn: {}\nTry `rpn --help` for more information.", e);
+            std::process::exit(2);
+        }
+    };
+    if let Some(table) = &options.table {
+        let success = match &options.input {
+            Input::File(path) => {
+                let data = read_file(path);
+                let lines = data.lines().map(|line| Ok(line.into())).enumerate();
+                csv::run(lines, table, &options)
             }
-            Ok(val) => println!("= {}", val),
+            _ => csv::run(io::stdin().lock().lines().enumerate(), table, &options),
         };
-        let _ = io::stdout().flush();
+        std::process::exit(i32::from(!success));
+    }
+
+    let success = match &options.input {
+        Input::Expressions(exprs) => run_lines(
+            text_lines(exprs.iter().cloned().map(Ok).enumerate()),
+            &options,
+        ),
+        Input::File(path) => {
+            let script = read_file(path);
+            let lines = script
+                .lines()
+                .map(|line| Ok(line.into()))
+                .enumerate()
+                .skip(usize::from(cli::has_shebang(&script)));
+            run_lines(text_lines(lines), &options)
+        }
+        #[cfg(feature = "serde")]
+        Input::Ast(path) => run_lines(program_lines(&read_file(path)), &options),
+        #[cfg(not(feature = "serde"))]
+        Input::Ast(_) => unreachable!("`--load-ast` is rejected without serde"),
+        Input::Stdin => run_lines(text_lines(io::stdin().lock().lines().enumerate()), &options),
+        Input::Auto if !atty::is(atty::Stream::Stdin) => {
+            run_lines(text_lines(io::stdin().lock().lines().enumerate()), &options)
+        }
+        Input::Auto => match repl::run(&options) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("rpn: {}", e);
+                false
+            }
+        },
+    };
+    if !success {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_text_lines() {
+        let script = "1 2 +\n\n  \n3 4 *\n";
+        let lines: Vec<(usize, String)> =
+            text_lines(script.lines().map(|line| Ok(line.into())).enumerate())
+                .map(|(index, line)| match line {
+                    Ok(Line::Text(text)) => (index, text),
+                    _ => panic!("line {} is not text", index),
+                })
+                .collect();
+        assert_eq!(lines, vec![(0, "1 2 +".into()), (3, "3 4 *".into())]);
     }
 }
@@ -0,0 +1,680 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use crate::csv;
+use crate::dates;
+use crate::interpreter::{EvalError, Number};
+use crate::tokenizer::Op;
+
+/// Decimal places kept by division, which is the only inexact operation.
+const MAX_SCALE: u32 = 12;
+/// Rates dated more than this many days before today are refused.
+pub const MAX_AGE: i64 = 7;
+
+/// ISO 4217 codes with the number of digits after the decimal point.
+const CURRENCIES: &[(&str, u32)] = &[
+    ("USD", 2),
+    ("EUR", 2),
+    ("GBP", 2),
+    ("JPY", 0),
+    ("CHF", 2),
+    ("CAD", 2),
+    ("AUD", 2),
+    ("NZD", 2),
+    ("CNY", 2),
+    ("HKD", 2),
+    ("SGD", 2),
+    ("SEK", 2),
+    ("NOK", 2),
+    ("DKK", 2),
+    ("PLN", 2),
+    ("CZK", 2),
+    ("HUF", 2),
+    ("RON", 2),
+    ("TRY", 2),
+    ("ILS", 2),
+    ("INR", 2),
+    ("KRW", 0),
+    ("THB", 2),
+    ("MXN", 2),
+    ("BRL", 2),
+    ("ZAR", 2),
+];
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "String", try_from = "String")
+)]
+pub struct Currency(usize);
+
+impl Currency {
+    /// Looks up a currency by its code, in upper or lower case.
+    pub fn parse(code: &str) -> Option<Currency> {
+        CURRENCIES
+            .iter()
+            .position(|(name, _)| *name == code || name.to_lowercase() == code)
+            .map(Currency)
+    }
+
+    pub fn code(&self) -> &'static str {
+        CURRENCIES[self.0].0
+    }
+
+    /// Digits after the decimal point in the smallest coin, two for cents.
+    fn minor_digits(&self) -> u32 {
+        CURRENCIES[self.0].1
+    }
+}
+
+impl Display for Currency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+impl From<Currency> for String {
+    fn from(value: Currency) -> Self {
+        value.code().into()
+    }
+}
+
+impl TryFrom<String> for Currency {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Currency::parse(&value).ok_or_else(|| format!("unknown currency `{}`", value))
+    }
+}
+
+/// An exact decimal number, `digits` over ten to the `scale`. Trailing zeros
+/// are always removed so equal values compare equal.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Decimal {
+    digits: i128,
+    scale: u32,
+}
+
+fn pow10(n: u32) -> Option<i128> {
+    10i128.checked_pow(n)
+}
+
+/// Rounds half away from zero.
+fn divide_rounded(n: i128, d: i128) -> Option<i128> {
+    let (quotient, remainder) = (n.checked_div(d)?, n.checked_rem(d)?);
+    match remainder.unsigned_abs() >= d.unsigned_abs() - remainder.unsigned_abs() {
+        true => quotient.checked_add(n.signum() * d.signum()),
+        false => Some(quotient),
+    }
+}
+
+impl Decimal {
+    fn new(mut digits: i128, mut scale: u32) -> Decimal {
+        while scale > 0 && digits % 10 == 0 {
+            digits /= 10;
+            scale -= 1;
+        }
+        Decimal { digits, scale }
+    }
+
+    /// Reads plain decimal notation, `-12.345`.
+    pub fn parse(text: &str) -> Option<Decimal> {
+        let unsigned = text.strip_prefix('-').unwrap_or(text);
+        let (int, frac) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        let all_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if int.len() + frac.len() == 0 || !all_digits(int) || !all_digits(frac) {
+            return None;
+        }
+        let digits: i128 = format!("{}{}", int, frac).parse().ok()?;
+        let digits = if unsigned.len() < text.len() {
+            -digits
+        } else {
+            digits
+        };
+        Some(Decimal::new(digits, u32::try_from(frac.len()).ok()?).round(MAX_SCALE))
+    }
+
+    /// Integers are exact, floats are taken at the shortest decimal that
+    /// reads back as the same float, so `0.1` is exactly a tenth.
+    pub fn from_number(n: &Number) -> Option<Decimal> {
+        match n {
+            Number::Int(n) => Some(Decimal::new(*n, 0)),
+            Number::Float(n) => Decimal::parse(&n.to_string()),
+            _ => None,
+        }
+    }
+
+    fn rescale(&self, scale: u32) -> Option<i128> {
+        self.digits.checked_mul(pow10(scale - self.scale)?)
+    }
+
+    pub fn add(&self, other: &Decimal) -> Option<Decimal> {
+        let scale = self.scale.max(other.scale);
+        let digits = self.rescale(scale)?.checked_add(other.rescale(scale)?)?;
+        Some(Decimal::new(digits, scale))
+    }
+
+    pub fn sub(&self, other: &Decimal) -> Option<Decimal> {
+        self.add(&other.neg()?)
+    }
+
+    pub fn mult(&self, other: &Decimal) -> Option<Decimal> {
+        let digits = self.digits.checked_mul(other.digits)?;
+        Some(Decimal::new(digits, self.scale + other.scale).round(MAX_SCALE))
+    }
+
+    /// Rounded to `MAX_SCALE` places, `None` when dividing by zero.
+    pub fn div(&self, other: &Decimal) -> Option<Decimal> {
+        if other.digits == 0 {
+            return None;
+        }
+        let shifted = self
+            .digits
+            .checked_mul(pow10(MAX_SCALE + other.scale - self.scale)?)?;
+        Some(Decimal::new(
+            divide_rounded(shifted, other.digits)?,
+            MAX_SCALE,
+        ))
+    }
+
+    pub fn neg(&self) -> Option<Decimal> {
+        Some(Decimal {
+            digits: self.digits.checked_neg()?,
+            scale: self.scale,
+        })
+    }
+
+    pub fn round(&self, scale: u32) -> Decimal {
+        match self.scale.checked_sub(scale).and_then(pow10) {
+            Some(divisor) if self.scale > scale => {
+                let digits = divide_rounded(self.digits, divisor)
+                    .expect("dividing by a power of ten can't overflow");
+                Decimal::new(digits, scale)
+            }
+            Some(_) => *self,
+            // Too small to show at this scale.
+            None if self.scale > scale => Decimal::new(0, 0),
+            None => *self,
+        }
+    }
+
+    pub fn to_f32(self) -> f32 {
+        (self.digits as f64 / 10f64.powi(self.scale as i32)) as f32
+    }
+
+    /// Written with exactly `places` digits after the point.
+    pub fn fixed(&self, places: u32) -> String {
+        let rounded = self.round(places);
+        // Padding with zeros rather than scaling the digits, which could
+        // overflow for large amounts.
+        let text = format!(
+            "{:0width$}{}",
+            rounded.digits.unsigned_abs(),
+            "0".repeat((places - rounded.scale) as usize),
+            width = rounded.scale as usize + 1
+        );
+        let (int, frac) = text.split_at(text.len() - places as usize);
+        let sign = if rounded.digits < 0 { "-" } else { "" };
+        match places {
+            0 => format!("{}{}", sign, int),
+            _ => format!("{}{}.{}", sign, int, frac),
+        }
+    }
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.fixed(self.scale))
+    }
+}
+
+/// An exact amount of one currency.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Money {
+    pub amount: Decimal,
+    pub currency: Currency,
+}
+
+impl Money {
+    pub fn new(n: &Number, currency: Currency) -> Result<Money, EvalError> {
+        match n {
+            Number::Int(_) | Number::Float(_) => Ok(Money {
+                amount: Decimal::from_number(n).ok_or(MoneyError::OutOfRange)?,
+                currency,
+            }),
+            Number::Money(_) => Err(MoneyError::NotAllowed(currency.code().to_lowercase()).into()),
+            n => Err(MoneyError::Mismatch(describe(n), describe_currency(currency)).into()),
+        }
+    }
+
+    /// The amount rounded to the currency's smallest coin.
+    pub fn shown(&self) -> String {
+        self.amount.fixed(self.currency.minor_digits())
+    }
+
+    pub fn neg(&self) -> Result<Money, EvalError> {
+        Ok(Money {
+            amount: self.amount.neg().ok_or(MoneyError::OutOfRange)?,
+            currency: self.currency,
+        })
+    }
+}
+
+impl Display for Money {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.shown(), self.currency)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MoneyError {
+    /// Currencies, or a currency and something else, that were combined.
+    Mismatch(String, String),
+    NotAllowed(String),
+    OutOfRange,
+    MissingRate(Currency),
+    /// A rate and the date it is from.
+    StaleRate(Currency, i64),
+}
+
+impl MoneyError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            MoneyError::Mismatch(_, _) => "E0031",
+            MoneyError::NotAllowed(_) => "E0032",
+            MoneyError::OutOfRange => "E0033",
+            MoneyError::MissingRate(_) => "E0034",
+            MoneyError::StaleRate(_, _) => "E0035",
+        }
+    }
+}
+
+impl Display for MoneyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoneyError::Mismatch(x, y) => write!(f, "can't mix {} with {}", x, y),
+            MoneyError::NotAllowed(name) => write!(f, "`{}` can't be used on money", name),
+            MoneyError::OutOfRange => write!(f, "amount is too large or divided by zero"),
+            MoneyError::MissingRate(currency) => write!(
+                f,
+                "no exchange rate for `{}`, load a table with `:rates load FILE`",
+                currency
+            ),
+            MoneyError::StaleRate(currency, date) => write!(
+                f,
+                "the `{}` rate is from {}, more than {} days ago",
+                currency,
+                dates::iso_date(*date),
+                MAX_AGE
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MoneyError {}
+
+impl From<MoneyError> for EvalError {
+    fn from(value: MoneyError) -> Self {
+        EvalError::Money(value)
+    }
+}
+
+fn describe_currency(currency: Currency) -> String {
+    format!("`{}`", currency)
+}
+
+fn describe(n: &Number) -> String {
+    match n {
+        Number::Money(x) => describe_currency(x.currency),
+        Number::Quantity(x) => format!("`{}`", x.unit_name()),
+        Number::Date(_) | Number::Duration(_) | Number::List(_) => dates::describe(n),
+        _ => "a plain number".into(),
+    }
+}
+
+fn is_plain(n: &Number) -> bool {
+    matches!(n, Number::Int(_) | Number::Float(_))
+}
+
+/// Arithmetic where at least one side is money. Amounts of the same currency
+/// add and divide, and can be scaled by plain numbers.
+pub fn calc(op: Op, x: &Number, y: &Number) -> Result<Number, EvalError> {
+    let mismatch = || MoneyError::Mismatch(describe(x), describe(y));
+    let amount = |n: &Number| Decimal::from_number(n).ok_or(MoneyError::OutOfRange);
+    let money = |amount: Option<Decimal>, currency| {
+        Ok(Number::Money(Money {
+            amount: amount.ok_or(MoneyError::OutOfRange)?,
+            currency,
+        }))
+    };
+    match (op, x, y) {
+        (Op::Add | Op::Sub, Number::Money(a), Number::Money(b)) if a.currency == b.currency => {
+            money(
+                match op {
+                    Op::Add => a.amount.add(&b.amount),
+                    _ => a.amount.sub(&b.amount),
+                },
+                a.currency,
+            )
+        }
+        (Op::Mult, Number::Money(a), n) | (Op::Mult, n, Number::Money(a)) if is_plain(n) => {
+            money(a.amount.mult(&amount(n)?), a.currency)
+        }
+        (Op::Div, Number::Money(a), n) if is_plain(n) => {
+            money(a.amount.div(&amount(n)?), a.currency)
+        }
+        (Op::Div, Number::Money(a), Number::Money(b)) if a.currency == b.currency => {
+            let ratio = a.amount.div(&b.amount).ok_or(MoneyError::OutOfRange)?;
+            Ok(Number::Float(ratio.to_f32()))
+        }
+        (Op::Add | Op::Sub | Op::Div, Number::Money(_), Number::Money(_)) => Err(mismatch().into()),
+        (Op::Add | Op::Sub, _, _) => Err(mismatch().into()),
+        (Op::Mult, Number::Quantity(_), _) | (Op::Mult, _, Number::Quantity(_)) => {
+            Err(mismatch().into())
+        }
+        (op, _, _) => Err(MoneyError::NotAllowed(op.to_string()).into()),
+    }
+}
+
+/// A rate is how much of a currency one unit of the table's base buys, and
+/// the day it was published.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Rate {
+    pub per_base: Decimal,
+    pub date: i64,
+}
+
+/// Exchange rates read from a file, never the network.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Rates {
+    pub rates: BTreeMap<Currency, Rate>,
+    /// Days since the epoch, to tell how old the rates are.
+    pub today: i64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RatesError {
+    Read(String),
+    /// A line that isn't a currency and its rate.
+    Syntax(usize, String),
+    UnknownCurrency(usize, String),
+    InvalidRate(usize, String),
+    InvalidDate(usize, String),
+    /// A TOML table without a `date`.
+    MissingDate,
+}
+
+impl Display for RatesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RatesError::Read(reason) => write!(f, "could not read rates: {}", reason),
+            RatesError::Syntax(line, text) => write!(
+                f,
+                "line {}: `{}` is not a currency, a rate and a date",
+                line, text
+            ),
+            RatesError::UnknownCurrency(line, code) => {
+                write!(f, "line {}: `{}` is not a known currency", line, code)
+            }
+            RatesError::InvalidRate(line, rate) => {
+                write!(f, "line {}: `{}` is not a positive rate", line, rate)
+            }
+            RatesError::InvalidDate(line, date) => {
+                write!(f, "line {}: `{}` is not a date like 2024-04-07", line, date)
+            }
+            RatesError::MissingDate => write!(f, "the rates need a `date = 2024-04-07` line"),
+        }
+    }
+}
+
+impl std::error::Error for RatesError {}
+
+fn currency(line: usize, code: &str) -> Result<Currency, RatesError> {
+    Currency::parse(code).ok_or_else(|| RatesError::UnknownCurrency(line, code.into()))
+}
+
+fn rate(line: usize, text: &str) -> Result<Decimal, RatesError> {
+    Decimal::parse(text)
+        .filter(|rate| rate.digits > 0)
+        .ok_or_else(|| RatesError::InvalidRate(line, text.into()))
+}
+
+fn date(line: usize, text: &str) -> Result<i64, RatesError> {
+    dates::parse_date(text).ok_or_else(|| RatesError::InvalidDate(line, text.into()))
+}
+
+/// Lines with their numbers, leaving out blank ones and `#` comments.
+fn content(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+}
+
+impl Rates {
+    /// Reads `currency,rate,date` rows, with an optional header row.
+    pub fn from_csv(text: &str, today: i64) -> Result<Rates, RatesError> {
+        let mut rates = BTreeMap::new();
+        for (index, (line, row)) in content(text).enumerate() {
+            let fields = csv::split(row, ',');
+            let [code, per_base, published] = fields.as_slice() else {
+                return Err(RatesError::Syntax(line, row.into()));
+            };
+            if index == 0 && Decimal::parse(per_base.trim()).is_none() {
+                continue;
+            }
+            let rate = Rate {
+                per_base: rate(line, per_base.trim())?,
+                date: date(line, published.trim())?,
+            };
+            rates.insert(currency(line, code.trim())?, rate);
+        }
+        Ok(Rates { rates, today })
+    }
+
+    /// Reads the small part of TOML a rate table needs:
+    ///
+    /// ```toml
+    /// date = 2024-04-05
+    /// base = "EUR"
+    ///
+    /// [rates]
+    /// USD = 1.0832
+    /// ```
+    pub fn from_toml(text: &str, today: i64) -> Result<Rates, RatesError> {
+        let (mut published, mut base, mut table) = (None, None, vec![]);
+        let mut in_rates = false;
+        for (line, row) in content(text) {
+            if row.starts_with('[') {
+                in_rates = row == "[rates]";
+                continue;
+            }
+            let Some((key, value)) = row.split_once('=') else {
+                return Err(RatesError::Syntax(line, row.into()));
+            };
+            let (key, value) = (key.trim(), value.trim().trim_matches('"'));
+            match (in_rates, key) {
+                (true, code) => table.push((currency(line, code)?, rate(line, value)?)),
+                (false, "date") => published = Some(date(line, value)?),
+                (false, "base") => base = Some(currency(line, value)?),
+                _ => {}
+            }
+        }
+        let published = published.ok_or(RatesError::MissingDate)?;
+        let one = Decimal::new(1, 0);
+        let rates = base
+            .map(|base| (base, one))
+            .into_iter()
+            .chain(table)
+            .map(|(currency, per_base)| {
+                let date = published;
+                (currency, Rate { per_base, date })
+            })
+            .collect();
+        Ok(Rates { rates, today })
+    }
+
+    /// Reads TOML when the path ends in `.toml` and CSV otherwise.
+    pub fn load(path: &str, today: i64) -> Result<Rates, RatesError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| RatesError::Read(format!("{}: {}", path, e)))?;
+        match path.ends_with(".toml") {
+            true => Rates::from_toml(&text, today),
+            false => Rates::from_csv(&text, today),
+        }
+    }
+
+    fn rate(&self, currency: Currency) -> Result<&Rate, MoneyError> {
+        let rate = self
+            .rates
+            .get(&currency)
+            .ok_or(MoneyError::MissingRate(currency))?;
+        match self.today - rate.date > MAX_AGE {
+            true => Err(MoneyError::StaleRate(currency, rate.date)),
+            false => Ok(rate),
+        }
+    }
+
+    /// Converts money to `to` through the base currency, multiplying first
+    /// so only the final division rounds.
+    pub fn exchange(&self, n: &Number, to: Currency) -> Result<Number, EvalError> {
+        let money = match n {
+            Number::Money(money) => money,
+            n => return Err(MoneyError::Mismatch(describe(n), describe_currency(to)).into()),
+        };
+        if money.currency == to {
+            return Ok(n.clone());
+        }
+        let (from_rate, to_rate) = (self.rate(money.currency)?, self.rate(to)?);
+        let amount = money
+            .amount
+            .mult(&to_rate.per_base)
+            .and_then(|amount| amount.div(&from_rate.per_base))
+            .ok_or(MoneyError::OutOfRange)?;
+        Ok(Number::Money(Money {
+            amount,
+            currency: to,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn decimal(text: &str) -> Decimal {
+        Decimal::parse(text).unwrap()
+    }
+
+    #[test]
+    fn test_decimal() {
+        assert_eq!(decimal("1.50"), Decimal::new(15, 1));
+        assert_eq!(decimal("-0.25").to_string(), "-0.25");
+        assert_eq!(decimal("12").to_string(), "12");
+        assert_eq!(Decimal::parse("1.2.3"), None);
+        assert_eq!(Decimal::parse("-"), None);
+        assert_eq!(Decimal::parse("1e3"), None);
+
+        // Binary floats would give 0.30000000000000004.
+        assert_eq!(decimal("0.1").add(&decimal("0.2")), Some(decimal("0.3")));
+        assert_eq!(decimal("1.1").mult(&decimal("1.1")), Some(decimal("1.21")));
+        assert_eq!(
+            decimal("1").div(&decimal("3")).unwrap().to_string(),
+            "0.333333333333"
+        );
+        assert_eq!(
+            decimal("2").div(&decimal("3")).unwrap().to_string(),
+            "0.666666666667"
+        );
+        assert_eq!(decimal("1").div(&decimal("0")), None);
+        let smallest = Decimal::new(i128::MIN, 0);
+        assert_eq!(smallest.neg(), None);
+        assert_eq!(smallest.div(&decimal("-1")), None);
+        assert_eq!(decimal("1").sub(&smallest), None);
+        assert_eq!(divide_rounded(-7, 2), Some(-4));
+        assert_eq!(divide_rounded(i128::MIN, -1), None);
+        assert_eq!(divide_rounded(5, i128::MIN), Some(0));
+
+        assert_eq!(decimal("2.345").fixed(2), "2.35");
+        assert_eq!(decimal("-2.345").fixed(2), "-2.35");
+        assert_eq!(decimal("0.05").fixed(0), "0");
+        assert_eq!(decimal("7").fixed(2), "7.00");
+        assert_eq!(decimal("-0.001").fixed(2), "0.00");
+        assert_eq!(
+            Decimal::new(i128::MIN + 1, 0).fixed(2),
+            format!("{}.00", i128::MIN + 1)
+        );
+        assert_eq!(
+            Decimal::from_number(&Number::Float(0.1)),
+            Some(decimal("0.1"))
+        );
+        assert_eq!(Decimal::from_number(&Number::Float(f32::NAN)), None);
+    }
+
+    #[test]
+    fn test_rates() {
+        let today = dates::parse_date("2024-04-07").unwrap();
+        let csv = "currency,rate,date\n\
+                   EUR,1,2024-04-05\n\
+                   # weekly\n\
+                   usd, 1.0832 ,2024-04-05\n\
+                   GBP,0.8571,2024-03-01\n";
+        let rates = Rates::from_csv(csv, today).unwrap();
+        let toml = "date = 2024-04-05\nbase = \"EUR\"\n\n[rates]\nUSD = 1.0832\n";
+        let from_toml = Rates::from_toml(toml, today).unwrap();
+        assert_eq!(rates.rates.len(), 3);
+        assert_eq!(from_toml.rates.len(), 2);
+
+        let [usd, eur, gbp, jpy] =
+            ["usd", "eur", "gbp", "jpy"].map(|c| Currency::parse(c).unwrap());
+        let hundred = Number::Money(Money::new(&Number::Int(100), usd).unwrap());
+        for rates in [&rates, &from_toml] {
+            let euros = rates.exchange(&hundred, eur).unwrap();
+            assert_eq!(euros.to_string(), "92.32 EUR");
+            assert_eq!(
+                rates.exchange(&euros, usd).unwrap().to_string(),
+                "100.00 USD"
+            );
+        }
+        assert_eq!(
+            rates.exchange(&hundred, gbp),
+            Err(MoneyError::StaleRate(gbp, today - 37).into())
+        );
+        assert_eq!(
+            rates.exchange(&hundred, jpy),
+            Err(MoneyError::MissingRate(jpy).into())
+        );
+        assert_eq!(
+            rates.exchange(&Number::Int(1), eur),
+            Err(MoneyError::Mismatch("a plain number".into(), "`EUR`".into()).into())
+        );
+
+        assert_eq!(
+            Rates::from_csv("XYZ,1,2024-04-05", today),
+            Err(RatesError::UnknownCurrency(1, "XYZ".into()))
+        );
+        assert_eq!(
+            Rates::from_csv("USD,1.2\n", today),
+            Err(RatesError::Syntax(1, "USD,1.2".into()))
+        );
+        assert_eq!(
+            Rates::from_csv("USD,0,2024-04-05", today),
+            Err(RatesError::InvalidRate(1, "0".into()))
+        );
+        assert_eq!(
+            Rates::from_csv("USD,1,2024-04-31", today),
+            Err(RatesError::InvalidDate(1, "2024-04-31".into()))
+        );
+        assert_eq!(
+            Rates::from_toml("[rates]\nUSD = 1", today),
+            Err(RatesError::MissingDate)
+        );
+        assert_eq!(
+            MoneyError::StaleRate(gbp, today - 37).to_string(),
+            "the `GBP` rate is from 2024-03-01, more than 7 days ago"
+        );
+    }
+}
@@ -0,0 +1,295 @@
+use crate::interpreter::EvalError;
+
+/// Steps are considered converged when smaller than this, relative to the
+/// size of the value.
+const TOLERANCE: f32 = 1e-6;
+const NEWTON_STEPS: usize = 50;
+const BRACKET_STEPS: usize = 60;
+const BISECTION_STEPS: usize = 200;
+/// How many times an integral's interval can be halved.
+const MAX_DEPTH: u32 = 16;
+
+/// Where to start looking for a root.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Start {
+    Guess(f32),
+    /// A root is only looked for between the two.
+    Interval(f32, f32),
+}
+
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() <= TOLERANCE * a.abs().max(b.abs()).max(1.0)
+}
+
+fn changes_sign(from: f32, to: f32) -> bool {
+    to.is_finite() && (to == 0.0 || to.signum() != from.signum())
+}
+
+/// Finds an `x` where `f(x)` is zero with Newton's method, using `slope` as
+/// the derivative. When Newton's method doesn't settle, or leaves the
+/// interval, it falls back to bisecting an interval where `f` changes sign,
+/// searching outwards from a guess for one.
+pub fn solve<F, D>(mut f: F, mut slope: D, start: Start) -> Result<f32, EvalError>
+where
+    F: FnMut(f32) -> Result<f32, EvalError>,
+    D: FnMut(f32) -> Result<f32, EvalError>,
+{
+    let mut steps = 0;
+    let (guess, bracket) = match start {
+        Start::Guess(x) => (x, None),
+        Start::Interval(a, b) => ((a + b) / 2.0, Some((a.min(b), a.max(b)))),
+    };
+
+    let mut x = guess;
+    while steps < NEWTON_STEPS {
+        steps += 1;
+        let fx = f(x)?;
+        if fx == 0.0 {
+            return Ok(x);
+        }
+        let next = x - fx / slope(x)?;
+        let outside = bracket.is_some_and(|(a, b)| next < a || next > b);
+        if !next.is_finite() || outside {
+            break;
+        }
+        if close(x, next) && f(next)?.is_finite() {
+            return Ok(next);
+        }
+        x = next;
+    }
+
+    let (mut a, mut b) = match bracket {
+        Some(bracket) => bracket,
+        None => match find_bracket(&mut f, guess, &mut steps)? {
+            Some(bracket) => bracket,
+            None => return Err(EvalError::NoConvergence(steps)),
+        },
+    };
+    let (mut fa, fb) = (f(a)?, f(b)?);
+    if fa == 0.0 {
+        return Ok(a);
+    }
+    if !changes_sign(fa, fb) {
+        return Err(EvalError::NoConvergence(steps));
+    }
+    // A sign change can also be a pole, like `1/x` across 0, where `f`
+    // grows instead of shrinking as the interval closes in.
+    let limit = fa.abs().max(fb.abs());
+    for _ in 0..BISECTION_STEPS {
+        steps += 1;
+        let mid = a + (b - a) / 2.0;
+        if close(a, b) || mid <= a || mid >= b {
+            return match f(mid)?.abs() <= limit {
+                true => Ok(mid),
+                false => Err(EvalError::NoConvergence(steps)),
+            };
+        }
+        let fm = f(mid)?;
+        if fm == 0.0 {
+            return Ok(mid);
+        }
+        if !fm.is_finite() {
+            return Err(EvalError::NoConvergence(steps));
+        }
+        if changes_sign(fa, fm) {
+            b = mid;
+        } else {
+            a = mid;
+            fa = fm;
+        }
+    }
+    Err(EvalError::NoConvergence(steps))
+}
+
+/// Widens an interval around `guess` until `f` changes sign across it.
+fn find_bracket<F>(
+    f: &mut F,
+    guess: f32,
+    steps: &mut usize,
+) -> Result<Option<(f32, f32)>, EvalError>
+where
+    F: FnMut(f32) -> Result<f32, EvalError>,
+{
+    let at_guess = f(guess)?;
+    let mut width = 0.1 * guess.abs().max(1.0);
+    for _ in 0..BRACKET_STEPS {
+        *steps += 1;
+        for x in [guess - width, guess + width] {
+            if changes_sign(at_guess, f(x)?) {
+                return Ok(Some((x.min(guess), x.max(guess))));
+            }
+        }
+        width *= 2.0;
+    }
+    Ok(None)
+}
+
+/// A stretch of an integral with the integrand at both ends and the middle,
+/// and Simpson's rule over it.
+struct Panel {
+    a: f64,
+    b: f64,
+    fa: f64,
+    fm: f64,
+    fb: f64,
+    whole: f64,
+}
+
+impl Panel {
+    fn new(a: f64, b: f64, fa: f64, fm: f64, fb: f64) -> Panel {
+        let whole = (b - a) / 6.0 * (fa + 4.0 * fm + fb);
+        Panel {
+            a,
+            b,
+            fa,
+            fm,
+            fb,
+            whole,
+        }
+    }
+}
+
+/// The integral of `f` from `a` to `b` by adaptive Simpson's rule, along with
+/// an estimate of its error. Halves are split again until each agrees with
+/// the whole to within its share of the tolerance.
+pub fn integrate<F>(mut f: F, a: f32, b: f32) -> Result<(f32, f32), EvalError>
+where
+    F: FnMut(f32) -> Result<f32, EvalError>,
+{
+    let mut f = |x: f64| Ok(f(x as f32)? as f64);
+    let (a, b) = (a as f64, b as f64);
+    let panel = Panel::new(a, b, f(a)?, f((a + b) / 2.0)?, f(b)?);
+    let tolerance = TOLERANCE as f64 * panel.whole.abs().max(1.0);
+    let mut error = 0.0;
+    let value = adapt(&mut f, &panel, tolerance, MAX_DEPTH, &mut error)?;
+    Ok((value as f32, error as f32))
+}
+
+fn adapt<F>(
+    f: &mut F,
+    panel: &Panel,
+    tolerance: f64,
+    depth: u32,
+    error: &mut f64,
+) -> Result<f64, EvalError>
+where
+    F: FnMut(f64) -> Result<f64, EvalError>,
+{
+    let m = (panel.a + panel.b) / 2.0;
+    let left = Panel::new(panel.a, m, panel.fa, f((panel.a + m) / 2.0)?, panel.fm);
+    let right = Panel::new(m, panel.b, panel.fm, f((m + panel.b) / 2.0)?, panel.fb);
+    let delta = left.whole + right.whole - panel.whole;
+    if depth == 0 || delta.abs() <= 15.0 * tolerance || !delta.is_finite() {
+        // Richardson extrapolation, the halves are 16 times as accurate.
+        *error += delta.abs() / 15.0;
+        return Ok(left.whole + right.whole + delta / 15.0);
+    }
+    Ok(adapt(f, &left, tolerance / 2.0, depth - 1, error)?
+        + adapt(f, &right, tolerance / 2.0, depth - 1, error)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ok(f: fn(f32) -> f32) -> impl FnMut(f32) -> Result<f32, EvalError> {
+        move |x| Ok(f(x))
+    }
+
+    #[test]
+    fn test_solve() {
+        let root = solve(ok(|x| x * x - 2.0), ok(|x| 2.0 * x), Start::Guess(1.0)).unwrap();
+        assert!((root - 2f32.sqrt()).abs() < 1e-6);
+
+        let root = solve(ok(|x| x * x - 2.0), ok(|x| 2.0 * x), Start::Guess(-1.0)).unwrap();
+        assert!((root + 2f32.sqrt()).abs() < 1e-6);
+
+        // Newton's first step from 0 would leave the interval.
+        let root = solve(
+            ok(|x| x.cos() - x),
+            ok(|x| -x.sin() - 1.0),
+            Start::Interval(0.0, 1.0),
+        )
+        .unwrap();
+        assert!((root - 0.7390851).abs() < 1e-5);
+
+        // A flat slope at the guess sends Newton's method nowhere, so the
+        // root is bracketed and bisected instead.
+        let root = solve(ok(|x| x * x * x - 8.0), ok(|_| 0.0), Start::Guess(0.0)).unwrap();
+        assert!((root - 2.0).abs() < 1e-5);
+
+        // Newton's method cycles between 0 and 1 here.
+        let root = solve(
+            ok(|x| x * x * x - 2.0 * x + 2.0),
+            ok(|x| 3.0 * x * x - 2.0),
+            Start::Guess(0.0),
+        )
+        .unwrap();
+        assert!((root + 1.7692924).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_integrate() {
+        let (value, error) = integrate(ok(|x| x * x), 0.0, 3.0).unwrap();
+        assert_eq!(value, 9.0);
+        assert!(error < 1e-6);
+
+        let (value, error) = integrate(ok(f32::sin), 0.0, std::f32::consts::PI).unwrap();
+        assert!((value - 2.0).abs() < 1e-5);
+        assert!(error < 1e-5);
+
+        // Most of the work goes where the integrand changes fastest.
+        let (value, _) = integrate(ok(|x| 1.0 / x), 0.001, 1.0).unwrap();
+        assert!((value - 1000f32.ln()).abs() < 1e-4);
+
+        let (value, _) = integrate(ok(|x| x * x), 3.0, 0.0).unwrap();
+        assert_eq!(value, -9.0);
+        assert_eq!(integrate(ok(|x| x), 1.0, 1.0), Ok((0.0, 0.0)));
+        assert_eq!(
+            integrate(|_| Err(EvalError::NothingToRecall), 0.0, 1.0),
+            Err(EvalError::NothingToRecall)
+        );
+    }
+
+    #[test]
+    fn test_solve_errors() {
+        // Newton's method stops at the flat point at 0 after two steps, then
+        // no sign change is ever found.
+        assert_eq!(
+            solve(ok(|x| x * x + 1.0), ok(|x| 2.0 * x), Start::Guess(1.0)),
+            Err(EvalError::NoConvergence(2 + BRACKET_STEPS))
+        );
+        assert_eq!(
+            EvalError::NoConvergence(62).to_string(),
+            "no root found after 62 iterations"
+        );
+        assert_eq!(
+            solve(
+                ok(|x| x * x + 1.0),
+                ok(|x| 2.0 * x),
+                Start::Interval(2.0, 3.0)
+            ),
+            Err(EvalError::NoConvergence(1))
+        );
+        // Poles change sign without a root, whether bisection lands on them
+        // or closes in on them.
+        for (a, b) in [(-1.0, 1.0), (-1.0, 2.0)] {
+            assert!(matches!(
+                solve(
+                    ok(|x| 1.0 / x),
+                    ok(|x| -1.0 / (x * x)),
+                    Start::Interval(a, b)
+                ),
+                Err(EvalError::NoConvergence(_))
+            ));
+        }
+        assert_eq!(
+            solve(
+                |_| Err(EvalError::NothingToRecall),
+                ok(|x| x),
+                Start::Guess(1.0)
+            ),
+            Err(EvalError::NothingToRecall)
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::interpreter::{self, Number};
+use crate::parser::Expr;
+use crate::tokenizer::{Func, Op, Token};
+
+/// What is known about variables before the program runs.
+struct Known<'a> {
+    vars: &'a BTreeMap<String, Number>,
+    /// Variables the program assigns, which may change type part way through.
+    stored: BTreeSet<String>,
+}
+
+/// Rewrites a program so it evaluates to the same values with less work.
+/// Operations on literals are worked out ahead of time and identities such
+/// as `x 0 +`, `x 1 *` and, for integers, `x 0 *` are simplified away. `vars`
+/// are the variables defined before the program runs.
+///
+/// Folded operations never happen, so `lastx` afterwards may recall a
+/// different operand than it would have.
+pub fn optimize(program: Vec<Expr>, vars: &BTreeMap<String, Number>) -> Vec<Expr> {
+    let mut known = Known {
+        vars,
+        stored: BTreeSet::new(),
+    };
+    for expr in &program {
+        collect_stores(expr, &mut known.stored);
+    }
+    program
+        .into_iter()
+        .map(|expr| simplify(expr, &known))
+        .collect()
+}
+
+fn collect_stores(expr: &Expr, stored: &mut BTreeSet<String>) {
+    match expr {
+        Expr::Store(name, value) => {
+            stored.insert(name.clone());
+            collect_stores(value, stored);
+        }
+        Expr::Calc(_, left, right) => {
+            collect_stores(left, stored);
+            collect_stores(right, stored);
+        }
+        Expr::Call(_, args) => args.iter().for_each(|arg| collect_stores(arg, stored)),
+        Expr::Literal(_) => {}
+    }
+}
+
+fn simplify(expr: Expr, known: &Known) -> Expr {
+    match expr {
+        Expr::Calc(op, left, right) => {
+            let left = simplify(*left, known);
+            let right = simplify(*right, known);
+            if let Some(folded) = fold(op, &left, &right).and_then(literal) {
+                return folded;
+            }
+            match (op, left, right) {
+                // Adding zero to a quantity is a dimension mismatch.
+                (Op::Add, x, Expr::Literal(Token::Int(0)))
+                | (Op::Add, Expr::Literal(Token::Int(0)), x)
+                | (Op::Sub, x, Expr::Literal(Token::Int(0)))
+                    if is_plain(&x, known) =>
+                {
+                    x
+                }
+                (Op::Mult, x, Expr::Literal(Token::Int(1)))
+                | (Op::Mult, Expr::Literal(Token::Int(1)), x)
+                | (Op::Div, x, Expr::Literal(Token::Int(1)))
+                    if is_scalar(&x, known) =>
+                {
+                    x
+                }
+                // A float times zero can be NaN, so only integers are dropped.
+                (Op::Mult, x, Expr::Literal(Token::Int(0)))
+                | (Op::Mult, Expr::Literal(Token::Int(0)), x)
+                    if is_int(&x, known) =>
+                {
+                    Expr::Literal(Token::Int(0))
+                }
+                (op, left, right) => Expr::Calc(op, Box::new(left), Box::new(right)),
+            }
+        }
+        Expr::Call(func, args) => {
+            let args: Vec<Expr> = args.into_iter().map(|arg| simplify(arg, known)).collect();
+            // Exchange rates, the clock and the statistics register are only
+            // known when evaluating.
+            let foldable =
+                !matches!(func, Func::Exchange(_) | Func::Now | Func::Today) && !func.uses_stats();
+            let folded = args
+                .iter()
+                .map(number)
+                .collect::<Option<Vec<_>>>()
+                .filter(|_| foldable)
+                .and_then(|values| interpreter::call(func, &values).ok())
+                .and_then(literal);
+            folded.unwrap_or(Expr::Call(func, args))
+        }
+        Expr::Store(name, value) => Expr::Store(name, Box::new(simplify(*value, known))),
+        Expr::Literal(_) => expr,
+    }
+}
+
+/// The value of an operation on two literals, unless working it out would
+/// fail, which is left for evaluation to report.
+pub fn fold(op: Op, left: &Expr, right: &Expr) -> Option<Number> {
+    let (x, y) = (number(left)?, number(right)?);
+    if let (Number::Int(a), Number::Int(b)) = (&x, &y) {
+        let fits = match op {
+            Op::Add => a.checked_add(*b).is_some(),
+            Op::Sub => a.checked_sub(*b).is_some(),
+            Op::Mult => a.checked_mul(*b).is_some(),
+            Op::Div => a.checked_div(*b).is_some(),
+            Op::Pow => true,
+        };
+        if !fits {
+            return None;
+        }
+    }
+    interpreter::calc(op, &x, &y).ok()
+}
+
+/// Whether `expr` is certain to evaluate to an integer without failing or
+/// assigning anything.
+fn is_int(expr: &Expr, known: &Known) -> bool {
+    match expr {
+        Expr::Literal(Token::Int(_)) => true,
+        Expr::Literal(Token::Symbol(name)) => {
+            !known.stored.contains(name) && matches!(known.vars.get(name), Some(Number::Int(_)))
+        }
+        Expr::Calc(Op::Add | Op::Sub | Op::Mult, left, right) => {
+            is_int(left, known) && is_int(right, known)
+        }
+        _ => false,
+    }
+}
+
+/// Whether `expr` can't evaluate to a quantity, money or a date.
+fn is_plain(expr: &Expr, known: &Known) -> bool {
+    match expr {
+        Expr::Literal(Token::Int(_) | Token::Float(_)) => true,
+        // Undefined variables fail either way.
+        Expr::Literal(Token::Symbol(name)) => {
+            !known.stored.contains(name)
+                && matches!(
+                    known.vars.get(name),
+                    None | Some(Number::Int(_) | Number::Float(_))
+                )
+        }
+        Expr::Calc(_, left, right) => is_plain(left, known) && is_plain(right, known),
+        Expr::Call(
+            Func::Unit(_)
+            | Func::Convert(_)
+            | Func::Currency(_)
+            | Func::Exchange(_)
+            | Func::Iso
+            | Func::Now
+            | Func::Today
+            | Func::Linreg
+            | Func::Factor,
+            _,
+        ) => false,
+        Expr::Call(func, args) => {
+            !func.takes_quote() && args.iter().all(|arg| is_plain(arg, known))
+        }
+        _ => false,
+    }
+}
+
+/// Whether `expr` can't evaluate to a date, a duration or a list, which
+/// can't be multiplied or divided, so `x 1 *` would hide an error.
+fn is_scalar(expr: &Expr, known: &Known) -> bool {
+    match expr {
+        Expr::Literal(Token::Int(_) | Token::Float(_)) => true,
+        Expr::Literal(Token::Symbol(name)) => {
+            !known.stored.contains(name)
+                && !matches!(
+                    known.vars.get(name),
+                    Some(Number::Date(_) | Number::Duration(_) | Number::List(_))
+                )
+        }
+        Expr::Calc(_, left, right) => is_scalar(left, known) && is_scalar(right, known),
+        Expr::Call(Func::Iso | Func::Now | Func::Today | Func::Linreg | Func::Factor, _) => false,
+        Expr::Call(func, args) => {
+            !func.takes_quote() && args.iter().all(|arg| is_scalar(arg, known))
+        }
+        _ => false,
+    }
+}
+
+pub fn number(expr: &Expr) -> Option<Number> {
+    match expr {
+        Expr::Literal(Token::Int(n)) => Some(Number::Int(*n)),
+        Expr::Literal(Token::Float(n)) => Some(Number::Float(*n)),
+        Expr::Literal(Token::Date(n)) => Some(Number::Date(*n)),
+        Expr::Literal(Token::Duration(n)) => Some(Number::Duration(*n)),
+        _ => None,
+    }
+}
+
+/// Quantities, money and lists can't be written as a literal.
+pub fn literal(n: Number) -> Option<Expr> {
+    match n {
+        Number::Int(n) => Some(Expr::Literal(Token::Int(n))),
+        Number::Float(n) => Some(Expr::Literal(Token::Float(n))),
+        Number::Date(n) => Some(Expr::Literal(Token::Date(n))),
+        Number::Duration(n) => Some(Expr::Literal(Token::Duration(n))),
+        Number::Quantity(_) | Number::Money(_) | Number::List(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::printer;
+    use crate::session::Session;
+
+    fn optimized(text: &str, session: &Session) -> String {
+        let program = optimize(session.parse(text.into()).unwrap(), &session.env.vars);
+        program
+            .iter()
+            .map(printer::rpn)
+            .collect::<Vec<_>>()
+            .join(" ; ")
+    }
+
+    #[test]
+    fn test_optimize() {
+        let mut session = Session::default();
+        session.run("3 =n ; 1.5 =f".into()).unwrap();
+        for (text, expected) in [
+            ("3 4 + 2 *", "14"),
+            ("2 3 ^ x *", "8 x *"),
+            ("16 sqrt neg y +", "-4.0 y +"),
+            ("x 0 + 1 * y 1 / -", "x y -"),
+            ("0 x 3 2 - * +", "x"),
+            ("x 0 -", "x"),
+            ("0 x -", "0 x -"),
+            ("x 0.0 +", "x 0.0 +"),
+            ("n 2 + 0 *", "0"),
+            ("f 0 *", "f 0 *"),
+            ("x 0 *", "x 0 *"),
+            ("5 =n ; n 0 *", "5 =n ; n 0 *"),
+            ("1 2 + =z ; z 4 *", "3 =z ; z 4 *"),
+            ("1 0 /", "1 0 /"),
+            ("1 2 + km >m", "3 km >m"),
+            ("x s 0 +", "x s 0 +"),
+            ("2147483647 1 +", "2147483648"),
+            (
+                "170141183460469231731687303715884105727 1 +",
+                "170141183460469231731687303715884105727 1 +",
+            ),
+            ("2024-04-07 1d 2 * +", "2024-04-09"),
+            ("now 1h + today -", "now 1h + today -"),
+            ("2024-04-07 =d ; d 0 +", "2024-04-07 =d ; d 0 +"),
+            ("2024-04-07 =d ; d 1 *", "2024-04-07 =d ; d 1 *"),
+            ("12 factor 1 *", "12 factor 1 *"),
+            ("2 ft 1 *", "2 ft"),
+        ] {
+            assert_eq!(optimized(text, &session), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_optimize_evaluates_the_same() {
+        let setup = "3 =n ; 1.5 =f ; -2 =w ; 2 ft =len ; 2024-04-07 =start";
+        for text in [
+            "3 4 + 2 *",
+            "n 0 + 1 * f 1 / -",
+            "n 2 + 0 * ; f 0 * ; 0 w *",
+            "2 3 ^ n * 10 /",
+            "16 sqrt neg f + 0 +",
+            "n 1 * =k ; k 0 + 2 ^",
+            "1 2 + =n ; n 0 *",
+            "f 2 ^ 0.5 ^ w 3 - 0 * -",
+            "len 1 * 0 +",
+            "3 in 0 - >ft",
+            "2 neg 1 * 3 2 1 - * /",
+            "start 0 + ; start 1d 1 * -",
+            "1 0 /",
+            "n n 3 - /",
+            "2024-04-07 =d ; d 1 *",
+            "start 1 / ; start 1d - 1 *",
+            "12 factor 1 * ; 1 12 factor *",
+            "len 1 / 1 *",
+        ] {
+            let mut plain = Session::default();
+            let mut fast = Session::default();
+            fast.optimize = true;
+            plain.run(setup.into()).unwrap();
+            fast.run(setup.into()).unwrap();
+            assert_eq!(fast.run(text.into()), plain.run(text.into()), "{}", text);
+            assert_eq!(fast.env.vars, plain.env.vars, "{}", text);
+        }
+    }
+}
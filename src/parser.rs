@@ -1,60 +1,509 @@
-use crate::tokenizer::{Op, Token};
+use std::collections::BTreeMap;
+use std::fmt::Display;
 
-#[derive(Debug, PartialEq)]
+use crate::infix::InfixError;
+use crate::tokenizer::{self, Field, Func, Op, Token, TokenParseError};
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
     Calc(Op, Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
+    Store(String, Box<Expr>),
     Literal(Token),
 }
 
 #[derive(Debug, PartialEq)]
 pub enum ParserError {
-    UnrecognizedToken,
+    UnrecognizedToken(String, TokenParseError),
     OperatorMissingOpperand(Op),
+    FunctionMissingArgument(Func),
     UnbalancedEquation,
     NoExpression,
+    StoreMissingValue(String),
+    RecursiveWord(String),
+    Infix(InfixError),
+    /// A builtin like `solve` that wasn't given its quotations.
+    QuoteArgument(Func),
+    /// A call in a tree read from elsewhere with the wrong number of
+    /// arguments.
+    ArgumentCount(Func, usize),
+}
+
+impl ParserError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParserError::UnrecognizedToken(_, reason) => reason.code(),
+            ParserError::OperatorMissingOpperand(_) | ParserError::FunctionMissingArgument(_) => {
+                "E0006"
+            }
+            ParserError::UnbalancedEquation => "E0007",
+            ParserError::NoExpression => "E0008",
+            ParserError::StoreMissingValue(_) => "E0013",
+            ParserError::RecursiveWord(_) => "E0014",
+            ParserError::Infix(reason) => reason.code(),
+            ParserError::QuoteArgument(_) => "E0025",
+            ParserError::ArgumentCount(_, _) => "E0024",
+        }
+    }
+}
+
+impl Display for ParserError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParserError::UnrecognizedToken(word, _) => write!(f, "unrecognized token `{}`", word),
+            ParserError::OperatorMissingOpperand(op) => {
+                write!(f, "operator `{}` is missing an operand", op)
+            }
+            ParserError::FunctionMissingArgument(func) => {
+                write!(f, "`{}` is missing an argument", func)
+            }
+            ParserError::UnbalancedEquation => {
+                write!(
+                    f,
+                    "expression leaves more than one value, an operator is missing"
+                )
+            }
+            ParserError::NoExpression => write!(f, "no expression to evaluate"),
+            ParserError::StoreMissingValue(name) => {
+                write!(f, "nothing to store in `{}`", name)
+            }
+            ParserError::RecursiveWord(name) => {
+                write!(f, "word `{}` is used in its own definition", name)
+            }
+            ParserError::Infix(_) => write!(f, "invalid infix expression"),
+            ParserError::QuoteArgument(func) => match tokenizer::builtin(&func.to_string()) {
+                Some(builtin) => write!(f, "`{}` is used like `{}`", func, builtin.example),
+                None => write!(f, "`{}` needs a quotation", func),
+            },
+            ParserError::ArgumentCount(func, found) => write!(
+                f,
+                "`{}` takes {} argument{}, not {}",
+                func,
+                func.arity(),
+                if func.arity() == 1 { "" } else { "s" },
+                found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParserError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParserError::UnrecognizedToken(_, reason) => Some(reason),
+            ParserError::Infix(reason) => Some(reason),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse(tokens: Vec<Token>) -> Result<Vec<Expr>, ParserError> {
+    build(tokens, false)
 }
 
-pub fn parse(tokens: Vec<Token>) -> Result<Expr, ParserError> {
+/// The values a quotation leaves behind, so `[ 0 2 ]` is two expressions
+/// rather than a missing operator.
+pub fn quoted(tokens: &[Token]) -> Result<Vec<Expr>, ParserError> {
+    build(tokens.to_vec(), true)
+}
+
+fn build(tokens: Vec<Token>, loose: bool) -> Result<Vec<Expr>, ParserError> {
+    let mut program = vec![];
     let mut stack: Vec<Expr> = vec![];
     for token in tokens.into_iter() {
         match token {
-            Token::Unrecognized => return Err(ParserError::UnrecognizedToken),
+            Token::Unrecognized(word, reason) => {
+                return Err(ParserError::UnrecognizedToken(word, reason))
+            }
+            Token::Separator => program.extend(finish(&mut stack, loose)?),
             Token::Operation(op) => {
                 let operand2 = stack
                     .pop()
-                    .ok_or(ParserError::OperatorMissingOpperand(op.clone()))?;
+                    .ok_or(ParserError::OperatorMissingOpperand(op))?;
                 let operand1 = stack
                     .pop()
-                    .ok_or(ParserError::OperatorMissingOpperand(op.clone()))?;
+                    .ok_or(ParserError::OperatorMissingOpperand(op))?;
 
                 stack.push(Expr::Calc(op, Box::new(operand1), Box::new(operand2)));
             }
+            Token::Function(func) => {
+                let at = stack
+                    .len()
+                    .checked_sub(func.arity())
+                    .ok_or(ParserError::FunctionMissingArgument(func))?;
+                let args = stack.split_off(at);
+                if func.takes_quote() && !quotes_fit(func, &args) {
+                    return Err(ParserError::QuoteArgument(func));
+                }
+                stack.push(Expr::Call(func, args));
+            }
+            Token::Store(name) => {
+                let value = stack
+                    .pop()
+                    .ok_or_else(|| ParserError::StoreMissingValue(name.clone()))?;
+                stack.push(Expr::Store(name, Box::new(value)));
+            }
             token => stack.push(Expr::Literal(token)),
         }
     }
-    match stack.len() {
-        1 => Ok(stack.pop().unwrap()),
+    program.extend(finish(&mut stack, loose)?);
+    match program.len() {
         0 => Err(ParserError::NoExpression),
+        _ => Ok(program),
+    }
+}
+
+/// Checks a program that was parsed elsewhere, like one read by
+/// `--load-ast`, holds to everything `parse` makes sure of.
+pub fn check(program: &[Expr]) -> Result<(), ParserError> {
+    if program.is_empty() {
+        return Err(ParserError::NoExpression);
+    }
+    program.iter().try_for_each(check_expr)
+}
+
+fn check_expr(expr: &Expr) -> Result<(), ParserError> {
+    match expr {
+        Expr::Calc(_, left, right) => check_expr(left).and(check_expr(right)),
+        Expr::Store(_, value) => check_expr(value),
+        Expr::Call(func, args) if args.len() != func.arity() => {
+            Err(ParserError::ArgumentCount(*func, args.len()))
+        }
+        Expr::Call(func, args) if func.takes_quote() && !quotes_fit(*func, args) => {
+            Err(ParserError::QuoteArgument(*func))
+        }
+        Expr::Call(_, args) => args.iter().try_for_each(check_expr),
+        Expr::Literal(token) => check_token(token),
+    }
+}
+
+fn check_token(token: &Token) -> Result<(), ParserError> {
+    match token {
+        Token::Unrecognized(word, reason) => {
+            Err(ParserError::UnrecognizedToken(word.clone(), *reason))
+        }
+        Token::Field(Field::Index(0)) => Err(ParserError::UnrecognizedToken(
+            token.to_string(),
+            TokenParseError::InvalidField,
+        )),
+        Token::Quote(body) => body.iter().try_for_each(check_token),
+        _ => Ok(()),
+    }
+}
+
+/// Replaces user defined words with their bodies. A quote followed by a
+/// store, like `[ 2 * ] =double`, defines a word instead of being kept.
+pub fn expand(
+    tokens: Vec<Token>,
+    words: &mut BTreeMap<String, Vec<Token>>,
+) -> Result<Vec<Token>, ParserError> {
+    let mut expanded = vec![];
+    expand_into(tokens, words, &mut vec![], &mut expanded)?;
+    Ok(expanded)
+}
+
+fn expand_into(
+    tokens: Vec<Token>,
+    words: &mut BTreeMap<String, Vec<Token>>,
+    active: &mut Vec<String>,
+    expanded: &mut Vec<Token>,
+) -> Result<(), ParserError> {
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Quote(body) if matches!(tokens.peek(), Some(Token::Store(_))) => {
+                if let Some(Token::Store(name)) = tokens.next() {
+                    words.insert(name, body);
+                }
+            }
+            // Quotations given to builtins like `solve` can use words too.
+            Token::Quote(body) => {
+                let mut inner = vec![];
+                expand_into(body, words, active, &mut inner)?;
+                expanded.push(Token::Quote(inner));
+            }
+            Token::Symbol(name) if words.contains_key(&name) => {
+                if active.contains(&name) {
+                    return Err(ParserError::RecursiveWord(name));
+                }
+                let body = words[&name].clone();
+                active.push(name);
+                expand_into(body, words, active, expanded)?;
+                active.pop();
+            }
+            token => expanded.push(token),
+        }
+    }
+    Ok(())
+}
+
+/// Closes off the expression between two separators. Empty segments, like a
+/// trailing separator, are skipped.
+fn finish(stack: &mut Vec<Expr>, loose: bool) -> Result<Vec<Expr>, ParserError> {
+    match stack.len() {
+        0 | 1 => Ok(stack.pop().into_iter().collect()),
+        _ if loose => Ok(std::mem::take(stack)),
         _ => Err(ParserError::UnbalancedEquation),
     }
 }
 
+/// How many values a quotation leaves, if `expr` is one that parses.
+fn quote_len(expr: &Expr) -> Option<usize> {
+    match expr {
+        Expr::Literal(Token::Quote(body)) => quoted(body).ok().map(|values| values.len()),
+        _ => None,
+    }
+}
+
+/// The first argument is always a quoted expression. `solve` takes a guess
+/// or a `[ low high ]` quotation after it, the others two bounds.
+fn quotes_fit(func: Func, args: &[Expr]) -> bool {
+    match (func, args) {
+        (Func::Solve, [body, start]) => {
+            quote_len(body) == Some(1)
+                && match start {
+                    Expr::Literal(Token::Quote(_)) => quote_len(start) == Some(2),
+                    _ => true,
+                }
+        }
+        (Func::Integrate | Func::Sum | Func::Product, [body, low, high]) => {
+            quote_len(body) == Some(1)
+                && [low, high]
+                    .iter()
+                    .all(|bound| !matches!(bound, Expr::Literal(Token::Quote(_))))
+        }
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn test_error_display() {
+        use std::error::Error;
+
+        let err = ParserError::UnrecognizedToken("2f".into(), TokenParseError::InvalidNumber);
+        assert_eq!(err.to_string(), "unrecognized token `2f`");
+        assert_eq!(err.code(), "E0003");
+        assert_eq!(err.source().unwrap().to_string(), "not a valid number");
+
+        let err = ParserError::OperatorMissingOpperand(Op::Mult);
+        assert_eq!(err.to_string(), "operator `*` is missing an operand");
+        assert_eq!(err.code(), "E0006");
+        assert!(err.source().is_none());
+    }
+
+    #[test]
+    fn test_expand() {
+        let mut words = BTreeMap::new();
+        let double = vec![Token::Int(2), Token::Operation(Op::Mult)];
+
+        assert_eq!(
+            expand(
+                vec![
+                    Token::Quote(double.clone()),
+                    Token::Store("double".into()),
+                    Token::Int(3),
+                    Token::Symbol("double".into()),
+                    Token::Symbol("x".into()),
+                ],
+                &mut words
+            ),
+            Ok(vec![
+                Token::Int(3),
+                Token::Int(2),
+                Token::Operation(Op::Mult),
+                Token::Symbol("x".into())
+            ])
+        );
+        assert_eq!(words.get("double"), Some(&double));
+
+        words.insert(
+            "quad".into(),
+            vec![
+                Token::Symbol("double".into()),
+                Token::Symbol("double".into()),
+            ],
+        );
+        assert_eq!(
+            expand(
+                vec![Token::Int(1), Token::Symbol("quad".into())],
+                &mut words
+            ),
+            Ok(vec![
+                Token::Int(1),
+                Token::Int(2),
+                Token::Operation(Op::Mult),
+                Token::Int(2),
+                Token::Operation(Op::Mult)
+            ])
+        );
+
+        words.insert("loop".into(), vec![Token::Symbol("loop".into())]);
+        assert_eq!(
+            expand(vec![Token::Symbol("loop".into())], &mut words),
+            Err(ParserError::RecursiveWord("loop".into()))
+        );
+
+        assert_eq!(
+            expand(vec![Token::Quote(double.clone())], &mut words),
+            Ok(vec![Token::Quote(double)])
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let sqrt = |args| Expr::Call(Func::Sqrt, args);
+        let two = Expr::Literal(Token::Int(2));
+        assert_eq!(check(&[sqrt(vec![two.clone()])]), Ok(()));
+        assert_eq!(check(&[]), Err(ParserError::NoExpression));
+        assert_eq!(
+            check(&[sqrt(vec![])]),
+            Err(ParserError::ArgumentCount(Func::Sqrt, 0))
+        );
+        assert_eq!(
+            check(&[Expr::Store(
+                "x".into(),
+                Box::new(sqrt(vec![two.clone(), two.clone()]))
+            )]),
+            Err(ParserError::ArgumentCount(Func::Sqrt, 2))
+        );
+        assert_eq!(
+            check(&[Expr::Literal(Token::Field(Field::Index(0)))]),
+            Err(ParserError::UnrecognizedToken(
+                "$0".into(),
+                TokenParseError::InvalidField
+            ))
+        );
+        // `solve` with an empty quotation, or a number where one belongs.
+        assert_eq!(
+            check(&[Expr::Call(
+                Func::Solve,
+                vec![Expr::Literal(Token::Quote(vec![])), two.clone()]
+            )]),
+            Err(ParserError::QuoteArgument(Func::Solve))
+        );
+        assert_eq!(
+            check(&[Expr::Call(Func::Sum, vec![two.clone(), two.clone(), two])]),
+            Err(ParserError::QuoteArgument(Func::Sum))
+        );
+    }
+
+    #[test]
+    fn test_parse_program() {
+        assert_eq!(
+            parse(vec![Token::Separator]),
+            Err(ParserError::NoExpression)
+        );
+        assert_eq!(
+            parse(vec![
+                Token::Int(1),
+                Token::Separator,
+                Token::Int(2),
+                Token::Int(3),
+                Token::Operation(Op::Mult),
+                Token::Separator,
+            ])
+            .unwrap(),
+            vec![
+                Expr::Literal(Token::Int(1)),
+                Expr::Calc(
+                    Op::Mult,
+                    Box::new(Expr::Literal(Token::Int(2))),
+                    Box::new(Expr::Literal(Token::Int(3)))
+                )
+            ]
+        );
+        assert_eq!(
+            parse(vec![
+                Token::Int(1),
+                Token::Int(2),
+                Token::Separator,
+                Token::Int(3),
+            ]),
+            Err(ParserError::UnbalancedEquation)
+        );
+        assert_eq!(
+            parse(vec![
+                Token::Int(1),
+                Token::Separator,
+                Token::Operation(Op::Add),
+            ]),
+            Err(ParserError::OperatorMissingOpperand(Op::Add))
+        );
+    }
+
     #[test]
     fn test_parse() {
         assert_eq!(parse(vec![]), Err(ParserError::NoExpression));
+        assert_eq!(
+            parse(vec![
+                Token::Int(1),
+                Token::Unrecognized("3-2".into(), TokenParseError::GluedOperator),
+                Token::Operation(Op::Add),
+            ]),
+            Err(ParserError::UnrecognizedToken(
+                "3-2".into(),
+                TokenParseError::GluedOperator
+            ))
+        );
         assert_eq!(
             parse(vec![Token::Int(1)]).unwrap(),
-            Expr::Literal(Token::Int(1))
+            vec![Expr::Literal(Token::Int(1))]
         );
         // TODO This error does not make sense in this case, change later
         assert_eq!(
             parse(vec![Token::Int(1), Token::Int(2)]),
             Err(ParserError::UnbalancedEquation)
         );
+        assert_eq!(
+            parse(vec![Token::Store("x".into())]),
+            Err(ParserError::StoreMissingValue("x".into()))
+        );
+        assert_eq!(
+            parse(vec![Token::Function(Func::Sqrt)]),
+            Err(ParserError::FunctionMissingArgument(Func::Sqrt))
+        );
+        assert_eq!(
+            parse(vec![
+                Token::Int(1),
+                Token::Int(2),
+                Token::Function(Func::Neg)
+            ]),
+            Err(ParserError::UnbalancedEquation)
+        );
+        assert_eq!(
+            parse(vec![
+                Token::Int(2),
+                Token::Function(Func::Sqrt),
+                Token::Function(Func::Neg)
+            ])
+            .unwrap(),
+            vec![Expr::Call(
+                Func::Neg,
+                vec![Expr::Call(Func::Sqrt, vec![Expr::Literal(Token::Int(2))])]
+            )]
+        );
+        assert_eq!(
+            parse(vec![
+                Token::Int(1),
+                Token::Store("x".into()),
+                Token::Symbol("x".into()),
+                Token::Operation(Op::Add)
+            ])
+            .unwrap(),
+            vec![Expr::Calc(
+                Op::Add,
+                Box::new(Expr::Store(
+                    "x".into(),
+                    Box::new(Expr::Literal(Token::Int(1)))
+                )),
+                Box::new(Expr::Literal(Token::Symbol("x".into())))
+            )]
+        );
         assert_eq!(
             parse(vec![Token::Int(1), Token::Operation(Op::Add)]),
             Err(ParserError::OperatorMissingOpperand(Op::Add))
@@ -85,11 +534,11 @@ mod test {
                 Token::Operation(Op::Add)
             ])
             .unwrap(),
-            Expr::Calc(
+            vec![Expr::Calc(
                 Op::Add,
                 Box::new(Expr::Literal(Token::Int(1))),
                 Box::new(Expr::Literal(Token::Int(2)))
-            )
+            )]
         );
         assert_eq!(
             parse(vec![
@@ -98,11 +547,11 @@ mod test {
                 Token::Operation(Op::Add)
             ])
             .unwrap(),
-            Expr::Calc(
+            vec![Expr::Calc(
                 Op::Add,
                 Box::new(Expr::Literal(Token::Int(-1))),
                 Box::new(Expr::Literal(Token::Int(2)))
-            )
+            )]
         );
         assert_eq!(
             parse(vec![
@@ -111,11 +560,11 @@ mod test {
                 Token::Operation(Op::Add)
             ])
             .unwrap(),
-            Expr::Calc(
+            vec![Expr::Calc(
                 Op::Add,
                 Box::new(Expr::Literal(Token::Int(-1))),
                 Box::new(Expr::Literal(Token::Int(-2)))
-            )
+            )]
         );
 
         assert_eq!(
@@ -125,11 +574,11 @@ mod test {
                 Token::Operation(Op::Add)
             ])
             .unwrap(),
-            Expr::Calc(
+            vec![Expr::Calc(
                 Op::Add,
                 Box::new(Expr::Literal(Token::Int(1))),
                 Box::new(Expr::Literal(Token::Float(2.1)))
-            )
+            )]
         );
         assert_eq!(
             parse(vec![
@@ -138,11 +587,11 @@ mod test {
                 Token::Operation(Op::Add)
             ])
             .unwrap(),
-            Expr::Calc(
+            vec![Expr::Calc(
                 Op::Add,
                 Box::new(Expr::Literal(Token::Float(1.5))),
                 Box::new(Expr::Literal(Token::Float(2.5)))
-            )
+            )]
         );
 
         assert_eq!(
@@ -154,7 +603,7 @@ mod test {
                 Token::Operation(Op::Sub)
             ])
             .unwrap(),
-            Expr::Calc(
+            vec![Expr::Calc(
                 Op::Sub,
                 Box::new(Expr::Literal(Token::Int(3))),
                 Box::new(Expr::Calc(
@@ -162,7 +611,7 @@ mod test {
                     Box::new(Expr::Literal(Token::Int(1))),
                     Box::new(Expr::Literal(Token::Int(2)))
                 ))
-            )
+            )]
         );
 
         assert_eq!(
@@ -176,7 +625,7 @@ mod test {
                 Token::Operation(Op::Add)
             ])
             .unwrap(),
-            Expr::Calc(
+            vec![Expr::Calc(
                 Op::Add,
                 Box::new(Expr::Calc(
                     Op::Mult,
@@ -188,7 +637,21 @@ mod test {
                     Box::new(Expr::Literal(Token::Int(4))),
                     Box::new(Expr::Literal(Token::Int(2)))
                 ))
-            )
+            )]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        use crate::tokenizer::tokenize;
+
+        let program = parse(tokenize("3 4.5 + =x ; $price 2 ^ sqrt neg".into())).unwrap();
+        let json = serde_json::to_string(&program).unwrap();
+        assert_eq!(serde_json::from_str::<Vec<Expr>>(&json).unwrap(), program);
+        assert_eq!(
+            serde_json::to_string(&parse(tokenize("1 2 -".into())).unwrap()).unwrap(),
+            r#"[{"Calc":["Sub",{"Literal":{"Int":1}},{"Literal":{"Int":2}}]}]"#
         );
     }
 }
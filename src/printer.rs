@@ -0,0 +1,187 @@
+use crate::parser::Expr;
+use crate::tokenizer::{Func, Op, Token};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Parens {
+    /// Around every operation, `(3 - (1 + 2))`.
+    Full,
+    /// Only where precedence or associativity needs them, `3 - (1 + 2)`.
+    Minimal,
+}
+
+/// How tightly an expression holds together when written infix, matching
+/// the infix parser.
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Store(_, _) => 0,
+        Expr::Calc(Op::Add | Op::Sub, _, _) => 1,
+        Expr::Calc(Op::Mult | Op::Div, _, _) => 2,
+        Expr::Call(Func::Neg, _) => 3,
+        Expr::Literal(Token::Int(n)) if *n < 0 => 3,
+        Expr::Literal(Token::Float(n)) if n.is_sign_negative() => 3,
+        Expr::Literal(Token::Duration(n)) if *n < 0 => 3,
+        Expr::Calc(Op::Pow, _, _) => 4,
+        Expr::Call(_, _) | Expr::Literal(_) => 5,
+    }
+}
+
+pub fn infix(expr: &Expr, parens: Parens) -> String {
+    match expr {
+        Expr::Literal(token) => token.to_string(),
+        Expr::Store(name, value) => wrap(
+            format!("{} = {}", name, infix(value, parens)),
+            parens == Parens::Full,
+        ),
+        Expr::Calc(op, left, right) => {
+            // `^` groups to the right, everything else to the left, so the
+            // other side needs parentheses at the same precedence.
+            let (left_tie, right_tie) = match op {
+                Op::Pow => (true, false),
+                _ => (false, true),
+            };
+            let text = format!(
+                "{} {} {}",
+                operand(left, expr, left_tie, parens),
+                op,
+                operand(right, expr, right_tie, parens)
+            );
+            wrap(text, parens == Parens::Full)
+        }
+        Expr::Call(Func::Neg, args) => {
+            let text = format!("-{}", operand(&args[0], expr, false, parens));
+            wrap(text, parens == Parens::Full)
+        }
+        Expr::Call(Func::Factorial, args) => {
+            format!("{}!", operand(&args[0], expr, false, parens))
+        }
+        // Like a constant, `now` rather than `now()`.
+        Expr::Call(func, args) if args.is_empty() => func.to_string(),
+        Expr::Call(func, args) => {
+            let args: Vec<String> = args.iter().map(|arg| infix(arg, parens)).collect();
+            format!("{}({})", func, args.join(", "))
+        }
+    }
+}
+
+fn operand(child: &Expr, parent: &Expr, tie: bool, parens: Parens) -> String {
+    let text = infix(child, parens);
+    let needed =
+        precedence(child) < precedence(parent) || (tie && precedence(child) == precedence(parent));
+    match parens {
+        Parens::Minimal => wrap(text, needed),
+        // Operations are already wrapped, only negative numbers are left.
+        Parens::Full => wrap(text, matches!(child, Expr::Literal(_)) && needed),
+    }
+}
+
+fn wrap(text: String, parens: bool) -> String {
+    match parens {
+        true => format!("({})", text),
+        false => text,
+    }
+}
+
+pub fn rpn(expr: &Expr) -> String {
+    match expr {
+        Expr::Literal(token) => token.to_string(),
+        Expr::Store(name, value) => format!("{} ={}", rpn(value), name),
+        Expr::Calc(op, left, right) => format!("{} {} {}", rpn(left), rpn(right), op),
+        Expr::Call(func, args) => {
+            let mut words: Vec<String> = args.iter().map(rpn).collect();
+            words.push(func.to_string());
+            words.join(" ")
+        }
+    }
+}
+
+pub fn sexpr(expr: &Expr) -> String {
+    match expr {
+        Expr::Literal(token) => token.to_string(),
+        Expr::Store(name, value) => format!("(set! {} {})", name, sexpr(value)),
+        Expr::Calc(op, left, right) => format!("({} {} {})", op, sexpr(left), sexpr(right)),
+        Expr::Call(func, args) => {
+            let mut words = vec![func.to_string()];
+            words.extend(args.iter().map(sexpr));
+            format!("({})", words.join(" "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::infix;
+    use crate::parser;
+    use crate::tokenizer::tokenize;
+
+    fn parse(text: &str) -> Expr {
+        parser::parse(tokenize(text.into())).unwrap().remove(0)
+    }
+
+    #[test]
+    fn test_infix() {
+        let expr = parse("3 1 2 + -");
+        assert_eq!(infix(&expr, Parens::Full), "(3 - (1 + 2))");
+        assert_eq!(infix(&expr, Parens::Minimal), "3 - (1 + 2)");
+
+        for (rpn, minimal) in [
+            ("1 2 - 3 -", "1 - 2 - 3"),
+            ("1 2 3 * +", "1 + 2 * 3"),
+            ("1 2 + 3 *", "(1 + 2) * 3"),
+            ("2 3 2 ^ ^", "2 ^ 3 ^ 2"),
+            ("2 3 ^ 2 ^", "(2 ^ 3) ^ 2"),
+            ("2 2 ^ neg", "-2 ^ 2"),
+            ("2 neg 2 ^", "(-2) ^ 2"),
+            ("-2 2 ^", "(-2) ^ 2"),
+            ("1 2 + neg", "-(1 + 2)"),
+            ("-2 -3 -", "-2 - -3"),
+            ("1 3 + 4 * sqrt", "sqrt((1 + 3) * 4)"),
+            ("3 4 + =x 2 *", "(x = 3 + 4) * 2"),
+            ("x 2 / =y", "y = x / 2"),
+            ("3 ! neg 2 ! ^", "(-3!) ^ 2!"),
+            ("3 neg ! 1 2 + ! *", "(-3)! * (1 + 2)!"),
+            ("10 3 ncr", "ncr(10, 3)"),
+        ] {
+            assert_eq!(infix(&parse(rpn), Parens::Minimal), minimal);
+        }
+        assert_eq!(
+            infix(&parse("2 neg 3 4 * =x +"), Parens::Full),
+            "((-2) + (x = (3 * 4)))"
+        );
+        assert_eq!(infix(&parse("-2 -3 -"), Parens::Full), "(-2 - -3)");
+        assert_eq!(infix(&parse("-2 2 ^"), Parens::Full), "((-2) ^ 2)");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // Printing as infix and reading it back gives the same tree.
+        for rpn in [
+            "3 1 2 + -",
+            "2 3 2 ^ ^",
+            "2 neg 2 ^",
+            "-2 2 ^",
+            "1 2 + neg 3 /",
+            "x 2 ^ sqrt pi *",
+            "2 3 ! ^ ! neg",
+        ] {
+            let expr = parse(rpn);
+            for parens in [Parens::Full, Parens::Minimal] {
+                let tokens = infix::to_rpn(&infix(&expr, parens)).unwrap();
+                let reparsed = parser::parse(tokens).unwrap().remove(0);
+                assert_eq!(
+                    infix(&reparsed, Parens::Minimal),
+                    infix(&expr, Parens::Minimal)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_rpn_and_sexpr() {
+        let expr = parse("3 1 2 + - sqrt =x");
+        assert_eq!(rpn(&expr), "3 1 2 + - sqrt =x");
+        assert_eq!(sexpr(&expr), "(set! x (sqrt (- 3 (+ 1 2))))");
+        assert_eq!(rpn(&parse("1.5 $2 *")), "1.5 $2 *");
+        assert_eq!(sexpr(&parse("1.5 $2 *")), "(* 1.5 $2)");
+    }
+}
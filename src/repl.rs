@@ -0,0 +1,244 @@
+use std::path::PathBuf;
+
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Config, Context, Editor, Helper};
+
+use crate::cli::{Options, OutputFormat};
+use crate::commands::{self, Outcome, COMMANDS};
+use crate::json;
+use crate::session::{Session, Syntax};
+use crate::tokenizer::{self, Token, TokenParseError, BUILTINS};
+use crate::{new_session, render, report, show_rpn};
+
+const PROMPT: &str = "$ ";
+const HISTORY_SIZE: usize = 1000;
+
+#[derive(Debug, PartialEq)]
+struct Completion {
+    name: String,
+    description: String,
+}
+
+/// Everything that can be typed as a word: builtins from the tokenizer plus
+/// whatever the session has defined so far.
+fn completions(session: &Session) -> Vec<Completion> {
+    let builtins = BUILTINS.iter().map(|builtin| Completion {
+        name: builtin.name.into(),
+        description: builtin.description.into(),
+    });
+    let vars = session.env.vars.iter().map(|(name, val)| Completion {
+        name: name.clone(),
+        description: format!("variable, currently {}", val),
+    });
+    let words = session.words.iter().map(|(name, body)| Completion {
+        name: name.clone(),
+        description: format!("word, {}", Token::Quote(body.clone())),
+    });
+    let commands = COMMANDS.iter().map(|(name, description)| Completion {
+        name: name.to_string(),
+        description: description.to_string(),
+    });
+    builtins.chain(vars).chain(words).chain(commands).collect()
+}
+
+struct ReplHelper {
+    completions: Vec<Completion>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos]
+            .rfind(|ch: char| ch.is_whitespace() || matches!(ch, '[' | ']' | '(' | ')' | ';' | ','))
+            .map_or(0, |at| at + 1);
+        let prefix = &line[start..pos];
+        let candidates = self
+            .completions
+            .iter()
+            .filter(|completion| completion.name.starts_with(prefix))
+            .map(|completion| Pair {
+                display: format!("{:<8} {}", completion.name, completion.description),
+                replacement: completion.name.clone(),
+            })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        if is_incomplete(ctx.input()) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+impl Helper for ReplHelper {}
+
+/// Input ending in `\` or with an unclosed `[` carries on to the next line.
+fn is_incomplete(input: &str) -> bool {
+    input.trim_end().ends_with('\\')
+        || tokenizer::tokenize(input.into()).iter().any(|token| {
+            matches!(
+                token,
+                Token::Unrecognized(_, TokenParseError::UnclosedQuote)
+            )
+        })
+}
+
+/// Joins the lines of a continued input back into a single line.
+fn join_lines(input: &str) -> String {
+    input
+        .lines()
+        .map(|line| line.trim_end().strip_suffix('\\').unwrap_or(line))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn history_path() -> Option<PathBuf> {
+    let dir = dirs::data_dir()?.join("rpn");
+    std::fs::create_dir_all(&dir).ok()?;
+    Some(dir.join("history"))
+}
+
+fn evaluate(session: &mut Session, syntax: Syntax, text: String, options: &Options) {
+    let format = options.format.unwrap_or(OutputFormat::Inline);
+    if options.show_rpn && format != OutputFormat::Json {
+        if let Some(rpn) = show_rpn(syntax, &text) {
+            println!("{}", rpn);
+        }
+    }
+    let result = session.run_as(syntax, text.clone());
+    match &result {
+        _ if format == OutputFormat::Json => println!("{}", json::line(&text, &result)),
+        Err(e) => println!("{}", report(e)),
+        Ok(vals) if vals.is_empty() => {}
+        Ok(vals) => println!("= {}", render(vals, format, &session.display)),
+    }
+}
+
+pub fn run(options: &Options) -> rustyline::Result<()> {
+    let config = Config::builder()
+        .max_history_size(HISTORY_SIZE)?
+        .history_ignore_dups(true)?
+        .build();
+    let mut editor: Editor<ReplHelper, DefaultHistory> = Editor::with_config(config)?;
+    let mut session = new_session(options);
+    editor.set_helper(Some(ReplHelper {
+        completions: completions(&session),
+    }));
+
+    let history = history_path();
+    if let Some(path) = &history {
+        // A missing history file just means this is the first session.
+        let _ = editor.load_history(path);
+    }
+
+    println!("Reverse polish notation calculator");
+    loop {
+        let input = match editor.readline(PROMPT) {
+            Ok(input) => input,
+            Err(ReadlineError::Eof) => break,
+            Err(ReadlineError::Interrupted) => {
+                println!("(use :quit or Ctrl-D to leave)");
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        if input.trim().is_empty() {
+            continue;
+        }
+        editor.add_history_entry(input.as_str())?;
+
+        match commands::parse(&input) {
+            Some(Ok(command)) => match command.run(&mut session) {
+                Outcome::Output(text) => println!("{}", text),
+                Outcome::Evaluate(syntax, text) => evaluate(&mut session, syntax, text, options),
+                Outcome::Quit => break,
+            },
+            Some(Err(e)) => println!("error: {}", e),
+            None => {
+                let syntax = session.syntax;
+                evaluate(&mut session, syntax, join_lines(&input), options)
+            }
+        };
+        if let Some(helper) = editor.helper_mut() {
+            helper.completions = completions(&session);
+        }
+    }
+
+    if let Some(path) = &history {
+        editor.save_history(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_complete() {
+        let mut session = Session::default();
+        session.run("2 =pie ; [ 2 * ] =double".into()).unwrap();
+        let helper = ReplHelper {
+            completions: completions(&session),
+        };
+        let history = DefaultHistory::new();
+        let ctx = Context::new(&history);
+
+        let (start, candidates) = helper.complete("1 pi", 4, &ctx).unwrap();
+        assert_eq!(start, 2);
+        assert_eq!(
+            candidates
+                .iter()
+                .map(|pair| pair.display.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "pi       ratio of a circle's circumference to its diameter",
+                "pie      variable, currently 2"
+            ]
+        );
+
+        let (_, candidates) = helper.complete(":he", 3, &ctx).unwrap();
+        assert_eq!(candidates[0].replacement, ":help");
+
+        let (start, candidates) = helper.complete("3 [do", 5, &ctx).unwrap();
+        assert_eq!(start, 3);
+        assert_eq!(candidates[0].replacement, "double");
+        assert_eq!(candidates[0].display, "double   word, [ 2 * ]");
+    }
+
+    #[test]
+    fn test_continuation() {
+        assert!(!is_incomplete("1 2 +"));
+        assert!(is_incomplete("1 2 \\"));
+        assert!(is_incomplete("1 2 \\  "));
+        assert!(!is_incomplete("1 2 \\\n+"));
+        assert!(is_incomplete("[ 2 *"));
+        assert!(is_incomplete("[ 2 * \n[ 1"));
+        assert!(!is_incomplete("[ 2 *\n] =double"));
+
+        assert_eq!(join_lines("1 2 +"), "1 2 +");
+        assert_eq!(join_lines("1 2 \\\n+ 3\\\n*"), "1 2  + 3 *");
+    }
+}
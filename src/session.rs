@@ -0,0 +1,617 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::format::DisplayMode;
+use crate::infix;
+use crate::interpreter::{self, Env, InterpretError, Number};
+use crate::optimizer;
+use crate::parser::{self, Expr, ParserError};
+use crate::tokenizer::{self, Token};
+
+const UNDO_LIMIT: usize = 100;
+
+/// How input lines are written.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Syntax {
+    #[default]
+    Rpn,
+    Infix,
+}
+
+impl Syntax {
+    /// Turns a line into RPN tokens, which is all the parser understands.
+    pub fn translate(&self, text: String) -> Result<Vec<Token>, ParserError> {
+        match self {
+            Syntax::Rpn => Ok(tokenizer::tokenize(text)),
+            Syntax::Infix => Ok(infix::to_rpn(&text)?),
+        }
+    }
+}
+
+/// What the last line parsed to and the value of each node, in the order
+/// `interpreter::eval_traced` records them.
+#[derive(Debug, Default)]
+pub struct Trace {
+    pub program: Vec<Expr>,
+    pub values: Vec<Number>,
+}
+
+/// Everything a user defines that outlives a single line of input.
+#[derive(Debug, Default)]
+pub struct Session {
+    pub env: Env,
+    pub words: BTreeMap<String, Vec<Token>>,
+    /// Every result produced so far, the most recent last.
+    pub stack: Vec<Number>,
+    pub display: DisplayMode,
+    pub syntax: Syntax,
+    /// Simplify each program before evaluating it, see `optimizer::optimize`.
+    pub optimize: bool,
+    pub trace: Trace,
+    undo: VecDeque<Snapshot>,
+    redo: Vec<Snapshot>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Snapshot {
+    env: Env,
+    words: BTreeMap<String, Vec<Token>>,
+    stack: Vec<Number>,
+}
+
+impl Session {
+    pub fn run(&mut self, text: String) -> Result<Vec<Number>, InterpretError> {
+        self.record(|session| session.execute(text))
+    }
+
+    /// Evaluates a program that was parsed elsewhere, as `--load-ast` does.
+    pub fn run_program(&mut self, program: Vec<Expr>) -> Result<Vec<Number>, InterpretError> {
+        parser::check(&program)?;
+        self.record(|session| session.evaluate(program))
+    }
+
+    /// Runs one line written in `syntax`, whatever the session's own is.
+    pub fn run_as(&mut self, syntax: Syntax, text: String) -> Result<Vec<Number>, InterpretError> {
+        let own = std::mem::replace(&mut self.syntax, syntax);
+        let result = self.run(text);
+        self.syntax = own;
+        result
+    }
+
+    /// Parses a line without evaluating it or defining any words.
+    pub fn parse(&self, text: String) -> Result<Vec<Expr>, ParserError> {
+        let tokens = self.syntax.translate(text)?;
+        parser::parse(parser::expand(tokens, &mut self.words.clone())?)
+    }
+
+    /// Records the current state so the next change can be undone.
+    pub fn checkpoint(&mut self) {
+        let snapshot = self.snapshot();
+        self.push_undo(snapshot);
+    }
+
+    /// Forgets the stack, variables, words and statistics but keeps the
+    /// undo history, exchange rates and clock.
+    pub fn reset(&mut self) {
+        self.checkpoint();
+        self.env = Env {
+            rates: std::mem::take(&mut self.env.rates),
+            clock: self.env.clock,
+            ..Env::default()
+        };
+        self.words.clear();
+        self.stack.clear();
+    }
+
+    /// Returns whether there was anything to undo.
+    pub fn undo(&mut self) -> bool {
+        match self.undo.pop_back() {
+            Some(snapshot) => {
+                let current = self.snapshot();
+                self.redo.push(current);
+                self.restore(snapshot);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns whether there was anything to redo.
+    pub fn redo(&mut self) -> bool {
+        match self.redo.pop() {
+            Some(snapshot) => {
+                let current = self.snapshot();
+                self.undo.push_back(current);
+                self.restore(snapshot);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            env: self.env.clone(),
+            words: self.words.clone(),
+            stack: self.stack.clone(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.env = snapshot.env;
+        self.words = snapshot.words;
+        self.stack = snapshot.stack;
+    }
+
+    fn push_undo(&mut self, snapshot: Snapshot) {
+        if self.undo.len() == UNDO_LIMIT {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(snapshot);
+        self.redo.clear();
+    }
+
+    /// Runs `change`, making it undoable if it changed anything.
+    fn record<F>(&mut self, change: F) -> Result<Vec<Number>, InterpretError>
+    where
+        F: FnOnce(&mut Self) -> Result<Vec<Number>, InterpretError>,
+    {
+        let before = self.snapshot();
+        let result = change(self);
+        if self.snapshot() != before {
+            self.push_undo(before);
+        }
+        result
+    }
+
+    fn execute(&mut self, text: String) -> Result<Vec<Number>, InterpretError> {
+        self.trace = Trace::default();
+        let tokens = self.syntax.translate(text)?;
+        let defines_words = tokens.iter().any(|token| matches!(token, Token::Quote(_)));
+        let tokens = parser::expand(tokens, &mut self.words)?;
+        if defines_words && tokens.is_empty() {
+            // Nothing is left to evaluate once the definitions are taken out.
+            return Ok(vec![]);
+        }
+        self.evaluate(parser::parse(tokens)?)
+    }
+
+    fn evaluate(&mut self, program: Vec<Expr>) -> Result<Vec<Number>, InterpretError> {
+        let program = match self.optimize {
+            true => optimizer::optimize(program, &self.env.vars),
+            false => program,
+        };
+        self.trace = Trace {
+            program,
+            values: vec![],
+        };
+        let vals: Vec<Number> = self
+            .trace
+            .program
+            .iter()
+            .map(|expr| interpreter::eval_traced(expr, &mut self.env, &mut self.trace.values))
+            .collect::<Result<_, _>>()?;
+        self.stack.extend(vals.iter().cloned());
+        Ok(vals)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dates;
+    use crate::format::Notation;
+    use crate::interpreter::EvalError;
+    use crate::money::Rates;
+    use crate::tokenizer::{Func, TokenParseError};
+
+    /// The values of a line as displayed, or the message of the evaluation
+    /// error it fails with.
+    fn shown(session: &mut Session, text: &str) -> String {
+        match session.run(text.into()) {
+            Ok(vals) => vals
+                .iter()
+                .map(|val| session.display.format(val))
+                .collect::<Vec<_>>()
+                .join(" ; "),
+            Err(InterpretError::EvalError(e)) => e.to_string(),
+            Err(e) => panic!("{}: {:?}", text, e),
+        }
+    }
+
+    #[test]
+    fn test_run() {
+        let mut session = Session::default();
+        assert_eq!(session.run("3 4 + =x".into()), Ok(vec![Number::Int(7)]));
+        assert_eq!(
+            session.run("x 2 * ; x".into()),
+            Ok(vec![Number::Int(14), Number::Int(7)])
+        );
+        assert_eq!(session.run("[ 2 * ] =double".into()), Ok(vec![]));
+        assert_eq!(session.run("x double".into()), Ok(vec![Number::Int(14)]));
+        assert_eq!(session.env.vars.get("x"), Some(&Number::Int(7)));
+        assert!(session.words.contains_key("double"));
+        assert_eq!(
+            session.stack,
+            vec![
+                Number::Int(7),
+                Number::Int(14),
+                Number::Int(7),
+                Number::Int(14)
+            ]
+        );
+
+        assert_eq!(
+            session.run("y 1 +".into()),
+            Err(EvalError::UnboundSymbol("y".into()).into())
+        );
+
+        assert_eq!(
+            session.run_as(Syntax::Infix, "(x + 1) * 2 ^ 3".into()),
+            Ok(vec![Number::Int(64)])
+        );
+        assert_eq!(session.syntax, Syntax::Rpn);
+        session.syntax = Syntax::Infix;
+        assert_eq!(
+            session.run("-sqrt(16); x".into()),
+            Ok(vec![Number::Float(-4.0), Number::Int(7)])
+        );
+        assert_eq!(
+            session.run("x 2 *".into()),
+            Err(ParserError::Infix(infix::InfixError::UnexpectedToken("2".into())).into())
+        );
+    }
+
+    #[test]
+    fn test_solve() {
+        let root = |session: &mut Session, text: &str| match session.run(text.into()) {
+            Ok(vals) => match vals[..] {
+                [Number::Float(x)] => x,
+                _ => panic!("{}: {:?}", text, vals),
+            },
+            Err(e) => panic!("{}: {:?}", text, e),
+        };
+        let mut session = Session::default();
+        assert_eq!(root(&mut session, "[ x 2 ^ 2 - ] 1 solve"), 2f32.sqrt());
+        assert_eq!(
+            root(&mut session, "[ x 2 ^ 2 - ] 1 neg solve"),
+            -(2f32.sqrt())
+        );
+        assert!((root(&mut session, "[ x cos x - ] [ 0 1 ] solve") - 0.7390851).abs() < 1e-6);
+
+        // Defined variables are constants, words are expanded and stores
+        // inside the quotation are forgotten.
+        session.run("4 =a ; [ a * ] =times_a".into()).unwrap();
+        assert_eq!(root(&mut session, "[ x times_a =y 1 - ] 3 solve"), 0.25);
+        assert!(!session.env.vars.contains_key("y"));
+        assert!(!session.env.vars.contains_key("x"));
+        // With every variable defined, the only one varies.
+        assert_eq!(root(&mut session, "[ a 3 - ] 0 solve"), 3.0);
+
+        assert_eq!(
+            session.run("[ x 2 ^ 1 + ] 1 solve".into()),
+            Err(EvalError::NoConvergence(62).into())
+        );
+        assert_eq!(
+            session.run("[ x z + ] 1 solve".into()),
+            Err(EvalError::FreeVariable(Func::Solve, vec!["x".into(), "z".into()]).into())
+        );
+        assert_eq!(
+            session.run("[ 2 ] 1 solve".into()),
+            Err(EvalError::FreeVariable(Func::Solve, vec![]).into())
+        );
+        for text in ["1 2 solve", "[ x 1 ] 1 solve", "[ x ] [ 1 ] solve"] {
+            assert_eq!(
+                session.run(text.into()),
+                Err(ParserError::QuoteArgument(Func::Solve).into()),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_integrate_and_series() {
+        let mut session = Session::default();
+        assert_eq!(
+            session.run("[ x 2 ^ ] 0 3 integrate ; ierr".into()),
+            Ok(vec![Number::Float(9.0), Number::Float(0.0)])
+        );
+        assert_eq!(
+            session.run("[ k 2 ^ ] 1 10 sum ; [ k ] 1 5 product".into()),
+            Ok(vec![Number::Int(385), Number::Int(120)])
+        );
+        assert_eq!(
+            session.run("[ 1.0 k / ] 1 4 sum".into()),
+            Ok(vec![Number::Float(2.0833335)])
+        );
+        assert_eq!(
+            session.run("[ k ] 5 1 sum ; [ k ] 5 1 product".into()),
+            Ok(vec![Number::Int(0), Number::Int(1)])
+        );
+        assert_eq!(
+            session.run("[ k ] 1 13 product".into()),
+            Ok(vec![Number::Int(6227020800)])
+        );
+        assert_eq!(
+            session.run("[ k ] 1 40 product".into()),
+            Err(EvalError::IntegerOverflow("*".into()).into())
+        );
+        assert_eq!(
+            session.run("[ k ] 1 2.5 sum".into()),
+            Err(EvalError::RangeBound(Func::Sum, Number::Float(2.5)).into())
+        );
+        assert_eq!(
+            session.run("[ k ] 1 1000000000000 sum".into()),
+            Err(EvalError::TooManyTerms(Func::Sum).into())
+        );
+        assert_eq!(
+            session.run("[ k ] 0 1000000 product".into()),
+            Err(EvalError::TooManyTerms(Func::Product).into())
+        );
+        assert_eq!(
+            session.run("[ k ] [ 1 ] 2 sum".into()),
+            Err(ParserError::QuoteArgument(Func::Sum).into())
+        );
+    }
+
+    #[test]
+    fn test_units() {
+        let mut session = Session::default();
+        session.display.notation = Notation::Sig(4);
+        for (text, expected) in [
+            ("5 m 3 s /", "1.667 m/s"),
+            ("1 ft >m", "0.3048 m"),
+            ("1 m 1 s +", "`m` and `s` measure different things"),
+            ("2 km 500 m + ; 3 ft 2 ^", "2.500 km ; 0.8361 m^2"),
+            ("100 kg 9.8 m * 1 s 2 ^ / >N", "980.0 N"),
+            ("1 h >min ; 90 min 1 h /", "60.00 min ; 1.500"),
+            ("5 mi =trip ; trip 2 * >km", "5.000 mi ; 16.09 km"),
+            ("4 m 2 ^ sqrt ; 2 s neg", "4.000 m ; -2.000 s"),
+            ("1 m sin", "`sin` can't take `m`"),
+            ("2 1 m ^", "`^` can't take `m`"),
+            ("3 >ft", "a plain number and `m` measure different things"),
+        ] {
+            assert_eq!(shown(&mut session, text), expected, "{}", text);
+        }
+        assert_eq!(
+            session.run_as(Syntax::Infix, "km(2) / h(1)".into()),
+            session.run("2 km 1 h /".into())
+        );
+        for name in ["m", "h", "s", "kg"] {
+            let store = format!("={}", name);
+            assert_eq!(
+                session.run(format!("3 {}", store)),
+                Err(ParserError::UnrecognizedToken(store, TokenParseError::InvalidName).into())
+            );
+        }
+    }
+
+    #[test]
+    fn test_money() {
+        let mut session = Session::default();
+        let today = dates::parse_date("2024-04-07").unwrap();
+        session.env.rates = Rates::from_csv(
+            "EUR,1,2024-04-05\nUSD,1.0832,2024-04-05\nJPY,163.95,2024-03-01",
+            today,
+        )
+        .unwrap();
+        for (text, expected) in [
+            ("0.1 usd 0.2 usd +", "0.30 USD"),
+            ("10 eur 3 /", "3.33 EUR"),
+            ("10 eur 3 / 3 *", "10.00 EUR"),
+            ("1234.5 usd 2 * ; 10 eur 4 eur /", "2469.00 USD ; 2.5"),
+            ("100 usd >eur", "92.32 EUR"),
+            ("100 usd >eur >usd", "100.00 USD"),
+            ("1 usd 1 eur +", "can't mix `USD` with `EUR`"),
+            ("1 usd 1 +", "can't mix `USD` with a plain number"),
+            ("2 usd 3 usd *", "`*` can't be used on money"),
+            ("4 usd sqrt", "`sqrt` can't be used on money"),
+            ("1 usd 0 /", "amount is too large or divided by zero"),
+            (
+                "-170141183460469231731687303715884105728 usd neg",
+                "amount is too large or divided by zero",
+            ),
+            (
+                "1 usd 1000000000000000000000000000000.0 * 100000000.0 *",
+                "100000000000000000000000000000000000000.00 USD",
+            ),
+            (
+                "1 usd 1000000000000000000000000000000.0 * 1000000000000.0 *",
+                "amount is too large or divided by zero",
+            ),
+            (
+                "100 usd >gbp",
+                "no exchange rate for `GBP`, load a table with `:rates load FILE`",
+            ),
+            (
+                "100 usd >jpy",
+                "the `JPY` rate is from 2024-03-01, more than 7 days ago",
+            ),
+        ] {
+            assert_eq!(shown(&mut session, text), expected, "{}", text);
+        }
+        assert_eq!(
+            session.run("1 usd".into()).unwrap()[0].to_string(),
+            "1.00 USD"
+        );
+
+        session.reset();
+        assert_eq!(session.env.rates.rates.len(), 3);
+    }
+
+    #[test]
+    fn test_dates() {
+        let mut session = Session::default();
+        // 2024-04-07T09:30:00Z
+        session.env.clock = Some(1712482200);
+        for (text, expected) in [
+            ("2024-04-07 30d +", "2024-05-07"),
+            ("2024-12-25 2024-04-07 -", "262d"),
+            ("2024-04-07 2024-04-07T18:00 -", "-18h"),
+            (
+                "2024-03-31 P1DT12H - ; 1h30m 2 *",
+                "2024-03-29T12:00:00 ; 3h",
+            ),
+            (
+                "2024-04-07 unix ; 1712448000 iso",
+                "1712448000 ; 2024-04-07",
+            ),
+            (
+                "now ; today ; now today -",
+                "2024-04-07T09:30:00 ; 2024-04-07 ; 9h30m",
+            ),
+            ("2025-01-01 today - 1d /", "269"),
+            (
+                "2024-04-07 2024-04-07 +",
+                "`+` can't take a date and a date",
+            ),
+            (
+                "2024-04-07 30 +",
+                "`+` can't take a date and a plain number",
+            ),
+            ("1d 1 m *", "`*` can't take a duration and `m`"),
+            // A number glued to `m` or `h` is a duration, with a space it
+            // is a quantity, and the two don't mix.
+            ("90m ; 90 m ; 1h ; 1 h", "1h30m ; 90 m ; 1h ; 1 h"),
+            ("5m 1 min +", "`+` can't take a duration and `min`"),
+            ("5m >min", "`>min` can't take a duration"),
+            ("2024-04-07 sqrt", "`sqrt` can't take a date"),
+            ("30d unix", "`unix` can't take a duration"),
+            ("1d 0 /", "date or duration is out of range"),
+        ] {
+            assert_eq!(shown(&mut session, text), expected, "{}", text);
+        }
+        assert_eq!(
+            session.run_as(Syntax::Infix, "today + 2w".into()),
+            Ok(vec![Number::Date(1712448000 + 14 * 86400)])
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut session = Session::default();
+        session.display.notation = Notation::Fix(3);
+        for (text, expected) in [
+            ("mean", "`mean` needs at least 1 sample, add them with `s+`"),
+            ("2 s+ ; 4 s+ ; 4 s+", "1 ; 2 ; 3"),
+            ("4 s+ 5 s+ +", "9"),
+            (
+                "mean ; median ; var ; sdev",
+                "3.800 ; 4.000 ; 1.200 ; 1.095",
+            ),
+            ("smin ; max ; 90 min >h", "2.000 ; 5.000 ; 1.500 h"),
+            (
+                "linreg",
+                "`linreg` needs at least 2 x/y pairs, add them with `xy+`",
+            ),
+            ("1 m s+", "`s+` can't take `m`"),
+            ("sclear ; 7 s+ ; mean", "0 ; 1 ; 7.000"),
+            ("sclear", "0"),
+            ("mean", "`mean` needs at least 1 sample, add them with `s+`"),
+        ] {
+            assert_eq!(shown(&mut session, text), expected, "{}", text);
+        }
+
+        session.reset();
+        for (text, expected) in [
+            ("1 3 xy+ ; 2 5 xy+ ; 3 7 xy+", "1 ; 2 ; 3"),
+            ("linreg ; 10 predict", "[ 2.000 1.000 1.000 ] ; 21.000"),
+            ("mean", "2.000"),
+            ("linreg =fit ; fit 1 +", "`+` can't take a list"),
+        ] {
+            assert_eq!(shown(&mut session, text), expected, "{}", text);
+        }
+        assert_eq!(
+            session.run_as(Syntax::Infix, "predict(4) - predict(3)".into()),
+            Ok(vec![Number::Float(2.0)])
+        );
+        session.undo();
+        assert_eq!(session.env.stats.samples.len(), 3);
+    }
+
+    #[test]
+    fn test_run_program() {
+        let mut session = Session::default();
+        let program = session.parse("3 sqrt =x".into()).unwrap();
+        assert_eq!(
+            session.run_program(program),
+            Ok(vec![Number::Float(3f32.sqrt())])
+        );
+
+        // Trees read with `--load-ast` are checked before anything runs.
+        let malformed = vec![Expr::Store(
+            "y".into(),
+            Box::new(Expr::Call(Func::Sqrt, vec![])),
+        )];
+        assert_eq!(
+            session.run_program(malformed),
+            Err(ParserError::ArgumentCount(Func::Sqrt, 0).into())
+        );
+        assert!(!session.env.vars.contains_key("y"));
+    }
+
+    #[test]
+    fn test_integers() {
+        let mut session = Session::default();
+        for (text, expected) in [
+            ("5 !", Ok(vec![Number::Int(120)])),
+            ("2 ^ 4! - 8!", Ok(vec![Number::Int(16736896)])),
+            ("lcm(4, 6) * gcd(12, -18)", Ok(vec![Number::Int(72)])),
+            (
+                "factor(modpow(2, 10, 1000))",
+                Ok(vec![Number::List([2, 2, 2, 3].map(Number::Int).to_vec())]),
+            ),
+            (
+                "(-3)!",
+                Err(InterpretError::EvalError(EvalError::OutOfDomain(
+                    Func::Factorial,
+                    Number::Int(-3),
+                ))),
+            ),
+        ] {
+            assert_eq!(
+                session.run_as(Syntax::Infix, text.into()),
+                expected,
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_undo() {
+        let mut session = Session::default();
+        assert!(!session.undo());
+
+        session.run("3 =x".into()).unwrap();
+        session.run("x 4 * =x".into()).unwrap();
+        assert_eq!(session.run("lastx".into()), Ok(vec![Number::Int(4)]));
+
+        assert!(session.undo());
+        assert!(session.undo());
+        assert_eq!(session.env.vars.get("x"), Some(&Number::Int(3)));
+        assert_eq!(session.stack, vec![Number::Int(3)]);
+        assert_eq!(session.env.lastx, None);
+
+        assert!(session.redo());
+        assert_eq!(session.env.vars.get("x"), Some(&Number::Int(12)));
+        assert_eq!(session.env.lastx, Some(Number::Int(4)));
+
+        session.run("1 =y".into()).unwrap();
+        assert!(!session.redo());
+
+        session.reset();
+        assert!(session.env.vars.is_empty());
+        assert!(session.undo());
+        assert_eq!(session.env.vars.get("y"), Some(&Number::Int(1)));
+
+        for i in 0..UNDO_LIMIT + 10 {
+            session.run(format!("{} =z", i)).unwrap();
+        }
+        let mut undone = 0;
+        while session.undo() {
+            undone += 1;
+        }
+        assert_eq!(undone, UNDO_LIMIT);
+    }
+}
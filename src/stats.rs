@@ -0,0 +1,211 @@
+use crate::interpreter::{EvalError, Number};
+use crate::tokenizer::Func;
+
+/// Samples entered with `s+` and `xy+`, like the Σ registers on HP
+/// calculators. Single values are statistics of the x values, the first of
+/// each pair.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Stats {
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Sample {
+    pub x: f64,
+    pub y: Option<f64>,
+}
+
+/// A least squares line through the x/y pairs.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Fit {
+    pub slope: f64,
+    pub intercept: f64,
+    /// The correlation coefficient.
+    pub r: f64,
+}
+
+impl Stats {
+    /// Returns how many samples there are now.
+    pub fn add(&mut self, x: f64, y: Option<f64>) -> usize {
+        self.samples.push(Sample { x, y });
+        self.samples.len()
+    }
+
+    fn xs(&self, func: Func, needed: usize) -> Result<Vec<f64>, EvalError> {
+        match self.samples.len() >= needed {
+            true => Ok(self.samples.iter().map(|sample| sample.x).collect()),
+            false => Err(EvalError::NotEnoughSamples(func, needed)),
+        }
+    }
+
+    pub fn mean(&self) -> Result<f64, EvalError> {
+        let xs = self.xs(Func::Mean, 1)?;
+        Ok(xs.iter().sum::<f64>() / xs.len() as f64)
+    }
+
+    pub fn median(&self) -> Result<f64, EvalError> {
+        let mut xs = self.xs(Func::Median, 1)?;
+        xs.sort_by(f64::total_cmp);
+        let mid = xs.len() / 2;
+        Ok(match xs.len() % 2 {
+            0 => (xs[mid - 1] + xs[mid]) / 2.0,
+            _ => xs[mid],
+        })
+    }
+
+    /// The sample variance, dividing by one less than the count.
+    pub fn var(&self) -> Result<f64, EvalError> {
+        let xs = self.xs(Func::Var, 2)?;
+        let mean = xs.iter().sum::<f64>() / xs.len() as f64;
+        let squares: f64 = xs.iter().map(|x| (x - mean).powi(2)).sum();
+        Ok(squares / (xs.len() - 1) as f64)
+    }
+
+    pub fn sdev(&self) -> Result<f64, EvalError> {
+        self.var()
+            .map(f64::sqrt)
+            .map_err(|_| EvalError::NotEnoughSamples(Func::Sdev, 2))
+    }
+
+    pub fn min(&self) -> Result<f64, EvalError> {
+        let xs = self.xs(Func::Min, 1)?;
+        Ok(xs.into_iter().fold(f64::INFINITY, f64::min))
+    }
+
+    pub fn max(&self) -> Result<f64, EvalError> {
+        let xs = self.xs(Func::Max, 1)?;
+        Ok(xs.into_iter().fold(f64::NEG_INFINITY, f64::max))
+    }
+
+    /// Fits the samples entered as pairs, ignoring single values.
+    pub fn linreg(&self, func: Func) -> Result<Fit, EvalError> {
+        let pairs: Vec<(f64, f64)> = self
+            .samples
+            .iter()
+            .filter_map(|sample| Some((sample.x, sample.y?)))
+            .collect();
+        if pairs.len() < 2 {
+            return Err(EvalError::NotEnoughSamples(func, 2));
+        }
+        let n = pairs.len() as f64;
+        let (mean_x, mean_y) = (
+            pairs.iter().map(|(x, _)| x).sum::<f64>() / n,
+            pairs.iter().map(|(_, y)| y).sum::<f64>() / n,
+        );
+        let (mut sxx, mut syy, mut sxy) = (0.0, 0.0, 0.0);
+        for (x, y) in &pairs {
+            sxx += (x - mean_x).powi(2);
+            syy += (y - mean_y).powi(2);
+            sxy += (x - mean_x) * (y - mean_y);
+        }
+        let slope = sxy / sxx;
+        Ok(Fit {
+            slope,
+            intercept: mean_y - slope * mean_x,
+            r: sxy / (sxx * syy).sqrt(),
+        })
+    }
+}
+
+/// Applies a statistics function, `s+` and `xy+` adding to the register
+/// and `sclear` emptying it.
+pub fn call(func: Func, args: &[Number], stats: &mut Stats) -> Result<Number, EvalError> {
+    let float = |x: f64| Ok(Number::Float(x as f32));
+    match (func, args) {
+        (Func::Accumulate, [x]) => Ok(count(stats.add(x.plain(func)? as f64, None))),
+        (Func::AccumulatePair, [x, y]) => {
+            let (x, y) = (x.plain(func)? as f64, y.plain(func)? as f64);
+            Ok(count(stats.add(x, Some(y))))
+        }
+        (Func::ClearStats, []) => {
+            stats.samples.clear();
+            Ok(count(0))
+        }
+        (Func::Mean, []) => float(stats.mean()?),
+        (Func::Median, []) => float(stats.median()?),
+        (Func::Var, []) => float(stats.var()?),
+        (Func::Sdev, []) => float(stats.sdev()?),
+        (Func::Min, []) => float(stats.min()?),
+        (Func::Max, []) => float(stats.max()?),
+        (Func::Linreg, []) => {
+            let fit = stats.linreg(func)?;
+            Ok(Number::List(
+                [fit.slope, fit.intercept, fit.r]
+                    .map(|x| Number::Float(x as f32))
+                    .to_vec(),
+            ))
+        }
+        (Func::Predict, [x]) => {
+            let fit = stats.linreg(func)?;
+            float(fit.slope * x.plain(func)? as f64 + fit.intercept)
+        }
+        _ => unreachable!("`{}` is not a statistics function", func),
+    }
+}
+
+fn count(n: usize) -> Number {
+    Number::Int(n as i128)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn register(samples: &[(f64, Option<f64>)]) -> Stats {
+        let mut stats = Stats::default();
+        for (x, y) in samples {
+            stats.add(*x, *y);
+        }
+        stats
+    }
+
+    #[test]
+    fn test_single() {
+        let stats = register(&[
+            (2.0, None),
+            (4.0, None),
+            (4.0, None),
+            (4.0, None),
+            (5.0, None),
+        ]);
+        assert_eq!(stats.mean(), Ok(3.8));
+        assert_eq!(stats.median(), Ok(4.0));
+        assert_eq!(stats.var(), Ok(1.2));
+        assert_eq!(stats.min(), Ok(2.0));
+        assert_eq!(stats.max(), Ok(5.0));
+        assert_eq!(register(&[(1.0, None), (4.0, None)]).median(), Ok(2.5));
+
+        let empty = Stats::default();
+        assert_eq!(
+            empty.mean(),
+            Err(EvalError::NotEnoughSamples(Func::Mean, 1))
+        );
+        assert_eq!(
+            register(&[(1.0, None)]).sdev(),
+            Err(EvalError::NotEnoughSamples(Func::Sdev, 2))
+        );
+    }
+
+    #[test]
+    fn test_linreg() {
+        let stats = register(&[
+            (1.0, Some(3.0)),
+            (2.0, Some(5.0)),
+            (3.0, Some(7.0)),
+            (10.0, None),
+        ]);
+        let fit = stats.linreg(Func::Linreg).unwrap();
+        assert_eq!((fit.slope, fit.intercept, fit.r), (2.0, 1.0, 1.0));
+
+        let fit = register(&[(1.0, Some(2.0)), (2.0, Some(1.0)), (3.0, Some(4.0))])
+            .linreg(Func::Linreg)
+            .unwrap();
+        assert_eq!(fit.slope, 1.0);
+        assert!((fit.r - 0.6546537).abs() < 1e-6);
+
+        assert_eq!(
+            register(&[(1.0, Some(2.0)), (2.0, None)]).linreg(Func::Predict),
+            Err(EvalError::NotEnoughSamples(Func::Predict, 2))
+        );
+    }
+}
@@ -0,0 +1,312 @@
+use crate::optimizer;
+use crate::parser::Expr;
+use crate::tokenizer::{Func, Op, Token};
+
+fn int(n: i128) -> Expr {
+    Expr::Literal(Token::Int(n))
+}
+
+fn calc(op: Op, left: Expr, right: Expr) -> Expr {
+    Expr::Calc(op, Box::new(left), Box::new(right))
+}
+
+fn call(func: Func, arg: Expr) -> Expr {
+    Expr::Call(func, vec![arg])
+}
+
+/// Whether `var` appears anywhere in `expr`.
+fn contains(expr: &Expr, var: &str) -> bool {
+    match expr {
+        Expr::Literal(Token::Symbol(name)) => name == var,
+        Expr::Literal(_) => false,
+        Expr::Store(_, value) => contains(value, var),
+        Expr::Calc(_, left, right) => contains(left, var) || contains(right, var),
+        Expr::Call(_, args) => args.iter().any(|arg| contains(arg, var)),
+    }
+}
+
+/// The derivative of `expr` with respect to the free variable `var`, every
+/// other variable being held constant, simplified.
+pub fn derivative(expr: &Expr, var: &str) -> Expr {
+    simplify(differentiate(expr, var))
+}
+
+fn differentiate(expr: &Expr, var: &str) -> Expr {
+    match expr {
+        Expr::Literal(Token::Symbol(name)) if name == var => int(1),
+        Expr::Literal(_) => int(0),
+        // Storing doesn't change the value.
+        Expr::Store(_, value) => differentiate(value, var),
+        Expr::Calc(op, u, v) => {
+            let (du, dv) = (differentiate(u, var), differentiate(v, var));
+            let (u, v) = (*u.clone(), *v.clone());
+            match op {
+                Op::Add | Op::Sub => calc(*op, du, dv),
+                Op::Mult => calc(
+                    Op::Add,
+                    calc(Op::Mult, du, v.clone()),
+                    calc(Op::Mult, u, dv),
+                ),
+                Op::Div => calc(
+                    Op::Div,
+                    calc(
+                        Op::Sub,
+                        calc(Op::Mult, du, v.clone()),
+                        calc(Op::Mult, u, dv),
+                    ),
+                    calc(Op::Pow, v, int(2)),
+                ),
+                // v u^(v - 1) u'
+                Op::Pow if !contains(&v, var) => calc(
+                    Op::Mult,
+                    calc(
+                        Op::Mult,
+                        v.clone(),
+                        calc(Op::Pow, u, calc(Op::Sub, v, int(1))),
+                    ),
+                    du,
+                ),
+                // u^v ln(u) v'
+                Op::Pow if !contains(&u, var) => calc(
+                    Op::Mult,
+                    calc(Op::Mult, expr.clone(), call(Func::Ln, u)),
+                    dv,
+                ),
+                // u^v (v' ln(u) + v u' / u)
+                Op::Pow => calc(
+                    Op::Mult,
+                    expr.clone(),
+                    calc(
+                        Op::Add,
+                        calc(Op::Mult, dv, call(Func::Ln, u.clone())),
+                        calc(Op::Div, calc(Op::Mult, v, du), u),
+                    ),
+                ),
+            }
+        }
+        // Treated as constants: the quotation's variable is bound inside it,
+        // and a root doesn't move with the starting point. `now` and `today`
+        // take nothing.
+        Expr::Call(func, _) if func.takes_quote() || func.arity() == 0 => int(0),
+        Expr::Call(func, args) => {
+            let u = args[0].clone();
+            let du = differentiate(&u, var);
+            // Chain rule, f'(u) u'.
+            let outer = match func {
+                // Linear, like `neg`.
+                Func::Neg
+                | Func::Unit(_)
+                | Func::Convert(_)
+                | Func::Currency(_)
+                | Func::Exchange(_)
+                | Func::Unix
+                | Func::Iso => return call(*func, du),
+                Func::Sqrt => return calc(Op::Div, du, calc(Op::Mult, int(2), expr.clone())),
+                Func::Ln => return calc(Op::Div, du, u),
+                Func::Tan => return calc(Op::Div, du, calc(Op::Pow, call(Func::Cos, u), int(2))),
+                Func::Sin => call(Func::Cos, u),
+                Func::Cos => call(Func::Neg, call(Func::Sin, u)),
+                Func::Exp => expr.clone(),
+                // The slope of the fitted line.
+                Func::Predict => calc(
+                    Op::Sub,
+                    call(Func::Predict, int(1)),
+                    call(Func::Predict, int(0)),
+                ),
+                // Counts of samples and integer functions, which don't vary
+                // smoothly.
+                Func::Accumulate
+                | Func::AccumulatePair
+                | Func::Factorial
+                | Func::Ncr
+                | Func::Npr
+                | Func::Gcd
+                | Func::Lcm
+                | Func::IsPrime
+                | Func::Factor
+                | Func::ModPow
+                | Func::ModInv => return int(0),
+                Func::Solve
+                | Func::Integrate
+                | Func::Sum
+                | Func::Product
+                | Func::Now
+                | Func::Today
+                | Func::ClearStats
+                | Func::Mean
+                | Func::Median
+                | Func::Var
+                | Func::Sdev
+                | Func::Min
+                | Func::Max
+                | Func::Linreg => {
+                    unreachable!("handled above")
+                }
+            };
+            calc(Op::Mult, outer, du)
+        }
+    }
+}
+
+fn is(expr: &Expr, n: i128) -> bool {
+    match expr {
+        Expr::Literal(Token::Int(m)) => *m == n,
+        Expr::Literal(Token::Float(m)) => *m == n as f32,
+        _ => false,
+    }
+}
+
+/// Algebraic clean up of a derivative: folds constants and drops the zeros
+/// and ones the rules leave behind. Unlike `optimizer::optimize` it treats
+/// variables as real numbers, so `x 0 *` is `0` whatever `x` holds.
+pub fn simplify(expr: Expr) -> Expr {
+    match expr {
+        Expr::Calc(op, left, right) => {
+            let (left, right) = (simplify(*left), simplify(*right));
+            match optimizer::fold(op, &left, &right).and_then(optimizer::literal) {
+                Some(folded) => folded,
+                None => simplify_calc(op, left, right),
+            }
+        }
+        Expr::Call(func, args) => {
+            let mut args: Vec<Expr> = args.into_iter().map(simplify).collect();
+            let negated = |arg: &Expr| {
+                optimizer::number(arg)
+                    .and_then(|n| n.neg().ok())
+                    .and_then(optimizer::literal)
+            };
+            match (func, args.as_mut_slice()) {
+                (Func::Neg, [Expr::Call(Func::Neg, inner)]) => inner.remove(0),
+                (Func::Neg, [arg]) => match negated(arg) {
+                    Some(n) => n,
+                    None => Expr::Call(func, args),
+                },
+                (Func::Ln, [Expr::Literal(Token::Float(x))]) if *x == std::f32::consts::E => int(1),
+                _ => Expr::Call(func, args),
+            }
+        }
+        Expr::Store(name, value) => Expr::Store(name, Box::new(simplify(*value))),
+        Expr::Literal(_) => expr,
+    }
+}
+
+fn simplify_calc(op: Op, left: Expr, right: Expr) -> Expr {
+    match (op, left, right) {
+        (Op::Add, x, zero) | (Op::Add, zero, x) if is(&zero, 0) => x,
+        (Op::Add, x, y) if x == y => simplify_calc(Op::Mult, int(2), x),
+        (Op::Add, x, Expr::Call(Func::Neg, mut y)) => calc(Op::Sub, x, y.remove(0)),
+        (Op::Sub, x, zero) if is(&zero, 0) => x,
+        (Op::Sub, zero, x) if is(&zero, 0) => simplify(call(Func::Neg, x)),
+        (Op::Sub, x, y) if x == y => int(0),
+        (Op::Sub, x, Expr::Call(Func::Neg, mut y)) => calc(Op::Add, x, y.remove(0)),
+        (Op::Mult, zero, _) | (Op::Mult, _, zero) if is(&zero, 0) => int(0),
+        (Op::Mult, one, x) | (Op::Mult, x, one) if is(&one, 1) => x,
+        (Op::Mult, minus_one, x) | (Op::Mult, x, minus_one) if is(&minus_one, -1) => {
+            simplify(call(Func::Neg, x))
+        }
+        (Op::Mult, Expr::Call(Func::Neg, mut x), y)
+        | (Op::Mult, y, Expr::Call(Func::Neg, mut x))
+            if optimizer::number(&y).is_none() =>
+        {
+            call(Func::Neg, simplify_calc(Op::Mult, y, x.remove(0)))
+        }
+        // Constants go in front, `2 * x` rather than `x * 2`, and are merged.
+        (Op::Mult, x, c) if optimizer::number(&c).is_some() && optimizer::number(&x).is_none() => {
+            simplify_calc(Op::Mult, c, x)
+        }
+        (Op::Mult, a, Expr::Calc(Op::Mult, b, x)) if optimizer::number(&b).is_some() => {
+            match optimizer::fold(Op::Mult, &a, &b).and_then(optimizer::literal) {
+                Some(c) => simplify_calc(Op::Mult, c, *x),
+                None => calc(Op::Mult, a, calc(Op::Mult, *b, *x)),
+            }
+        }
+        (Op::Div, x, one) if is(&one, 1) => x,
+        (Op::Div, zero, _) if is(&zero, 0) => int(0),
+        (Op::Div, x, y) if x == y => int(1),
+        (Op::Pow, x, one) if is(&one, 1) => x,
+        (Op::Pow, _, zero) if is(&zero, 0) => int(1),
+        (op, left, right) => calc(op, left, right),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::{self, Env, Number};
+    use crate::printer::{self, Parens};
+    use crate::session::Session;
+
+    fn parse(text: &str) -> Expr {
+        Session::default().parse(text.into()).unwrap().remove(0)
+    }
+
+    fn d(text: &str) -> String {
+        printer::infix(&derivative(&parse(text), "x"), Parens::Minimal)
+    }
+
+    #[test]
+    fn test_derivative() {
+        for (rpn, expected) in [
+            ("x 2 ^ 3 x * +", "2 * x + 3"),
+            ("5 y *", "0"),
+            ("x y * x -", "y - 1"),
+            ("x x *", "2 * x"),
+            ("1 x /", "-1 / x ^ 2"),
+            ("x x 1 + /", "(x + 1 - x) / (x + 1) ^ 2"),
+            ("x sin x cos *", "cos(x) * cos(x) - sin(x) * sin(x)"),
+            ("x 2 * sin", "2 * cos(2 * x)"),
+            ("x cos neg", "sin(x)"),
+            ("x 2 ^ 1 + sqrt", "2 * x / (2 * sqrt(x ^ 2 + 1))"),
+            ("x ln", "1 / x"),
+            ("x tan", "1 / cos(x) ^ 2"),
+            ("x 3 * exp", "3 * exp(3 * x)"),
+            ("2 x ^", "2 ^ x * ln(2)"),
+            ("e x ^", "2.7182817 ^ x"),
+            ("x x ^", "x ^ x * (ln(x) + 1)"),
+            ("x 0.5 ^", "0.5 * x ^ (-0.5)"),
+            ("x 3 * =y", "3"),
+        ] {
+            assert_eq!(d(rpn), expected, "{}", rpn);
+        }
+    }
+
+    #[test]
+    fn test_derivative_matches_slope() {
+        // Compare against the slope of a small secant around a point.
+        let at = |expr: &Expr, x: f32| {
+            let mut env = Env::default();
+            env.vars.insert("x".into(), Number::Float(x));
+            env.vars.insert("y".into(), Number::Float(0.7));
+            match interpreter::eval(expr, &mut env).unwrap() {
+                Number::Float(n) => n,
+                Number::Int(n) => n as f32,
+                n => panic!("{} is not a plain number", n),
+            }
+        };
+        for rpn in [
+            "x 3 ^ y x * -",
+            "x sin x 2 ^ /",
+            "x ln x sqrt *",
+            "x x ^",
+            "x 2 * cos exp",
+            "1 x tan 2 ^ +",
+            "x y ^ neg",
+        ] {
+            let expr = parse(rpn);
+            let slope = derivative(&expr, "x");
+            for x in [0.5, 1.3, 2.1] {
+                let h = 1e-2;
+                let secant = (at(&expr, x + h) - at(&expr, x - h)) / (2.0 * h);
+                let exact = at(&slope, x);
+                assert!(
+                    (secant - exact).abs() < 1e-2 * exact.abs().max(1.0),
+                    "{} at {}: {} vs {}",
+                    rpn,
+                    x,
+                    secant,
+                    exact
+                );
+            }
+        }
+    }
+}
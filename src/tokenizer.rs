@@ -1,72 +1,745 @@
+use std::fmt::Display;
 use std::num::{ParseFloatError, ParseIntError};
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+use crate::dates;
+use crate::money::Currency;
+use crate::units::Unit;
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Token {
-    Int(i32),
+    Int(i128),
     Float(f32),
+    /// Seconds since 1970-01-01 UTC, written `2024-04-07` or
+    /// `2024-04-07T09:30`.
+    Date(i64),
+    /// Seconds, written `30d`, `1h30m` or `P1DT12H`.
+    Duration(i64),
     Operation(Op),
-    Unrecognized,
+    Function(Func),
+    Separator,
+    Symbol(String),
+    Store(String),
+    Quote(Vec<Token>),
+    LastX,
+    Field(Field),
+    Unrecognized(String, TokenParseError),
 }
 
-#[derive(Debug)]
+/// Why a word could not be turned into a token.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TokenParseError {
     ParseFloatError,
     IntParseError,
+    InvalidNumber,
+    UnknownWord,
+    GluedOperator,
+    InvalidName,
+    UnclosedQuote,
+    UnmatchedBracket,
+    InvalidField,
+    InvalidDate,
+}
+
+/// A reference to a column of the current row, `$2` or `$name`. Columns are
+/// numbered from 1 like in awk.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Field {
+    Index(usize),
+    Name(String),
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Op {
     Add,
     Sub,
     Mult,
     Div,
+    Pow,
+}
+
+/// Builtins that aren't written between their operands in infix.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Func {
+    Neg,
+    Sqrt,
+    Sin,
+    Cos,
+    Tan,
+    Ln,
+    Exp,
+    Solve,
+    Integrate,
+    Sum,
+    Product,
+    /// Gives a number units, `5 km`.
+    Unit(Unit),
+    /// Shows a quantity in other units of the same dimension, `>ft`.
+    Convert(Unit),
+    /// Makes a number an amount of money, `100 usd`.
+    Currency(Currency),
+    /// Converts money at the loaded exchange rates, `>eur`.
+    Exchange(Currency),
+    Unix,
+    Iso,
+    Now,
+    Today,
+    /// Adds a sample to the statistics register, `s+`.
+    Accumulate,
+    /// Adds an x/y pair, `xy+`.
+    AccumulatePair,
+    /// Empties the statistics register, `sclear`.
+    ClearStats,
+    Mean,
+    Median,
+    Var,
+    Sdev,
+    Min,
+    Max,
+    Linreg,
+    Predict,
+    /// Written after its operand in infix, `5!`.
+    Factorial,
+    Ncr,
+    Npr,
+    Gcd,
+    Lcm,
+    IsPrime,
+    /// Prime factors as a list.
+    Factor,
+    ModPow,
+    ModInv,
+}
+
+impl Func {
+    pub fn arity(&self) -> usize {
+        match self {
+            Func::Neg
+            | Func::Sqrt
+            | Func::Sin
+            | Func::Cos
+            | Func::Tan
+            | Func::Ln
+            | Func::Exp
+            | Func::Unit(_)
+            | Func::Convert(_)
+            | Func::Currency(_)
+            | Func::Exchange(_)
+            | Func::Unix
+            | Func::Iso
+            | Func::Accumulate
+            | Func::Predict
+            | Func::Factorial
+            | Func::IsPrime
+            | Func::Factor => 1,
+            Func::Solve
+            | Func::AccumulatePair
+            | Func::Ncr
+            | Func::Npr
+            | Func::Gcd
+            | Func::Lcm
+            | Func::ModInv => 2,
+            Func::Integrate | Func::Sum | Func::Product | Func::ModPow => 3,
+            Func::Now
+            | Func::Today
+            | Func::ClearStats
+            | Func::Mean
+            | Func::Median
+            | Func::Var
+            | Func::Sdev
+            | Func::Min
+            | Func::Max
+            | Func::Linreg => 0,
+        }
+    }
+
+    /// Whether the first argument is a quotation in a free variable rather
+    /// than a number.
+    pub fn takes_quote(&self) -> bool {
+        matches!(
+            self,
+            Func::Solve | Func::Integrate | Func::Sum | Func::Product
+        )
+    }
+
+    /// Whether it reads or adds to the statistics register.
+    pub fn uses_stats(&self) -> bool {
+        matches!(
+            self,
+            Func::Accumulate
+                | Func::AccumulatePair
+                | Func::ClearStats
+                | Func::Mean
+                | Func::Median
+                | Func::Var
+                | Func::Sdev
+                | Func::Min
+                | Func::Max
+                | Func::Linreg
+                | Func::Predict
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BuiltinKind {
+    Operation(Op),
+    Function(Func),
+    Constant(f32),
+    LastX,
+}
+
+impl BuiltinKind {
+    /// How many values the builtin takes from the expression before it.
+    pub fn arity(&self) -> usize {
+        match self {
+            BuiltinKind::Operation(_) => 2,
+            BuiltinKind::Function(func) => func.arity(),
+            BuiltinKind::Constant(_) | BuiltinKind::LastX => 0,
+        }
+    }
+}
+
+/// A word the tokenizer knows about without it being defined by the user.
+pub struct Builtin {
+    pub name: &'static str,
+    pub kind: BuiltinKind,
+    pub description: &'static str,
+    pub example: &'static str,
+}
+
+pub const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "+",
+        kind: BuiltinKind::Operation(Op::Add),
+        description: "add two numbers",
+        example: "3 4 +",
+    },
+    Builtin {
+        name: "-",
+        kind: BuiltinKind::Operation(Op::Sub),
+        description: "subtract the second number from the first",
+        example: "10 4 -",
+    },
+    Builtin {
+        name: "*",
+        kind: BuiltinKind::Operation(Op::Mult),
+        description: "multiply two numbers",
+        example: "3 4 *",
+    },
+    Builtin {
+        name: "/",
+        kind: BuiltinKind::Operation(Op::Div),
+        description: "divide the first number by the second",
+        example: "10 4 /",
+    },
+    Builtin {
+        name: "^",
+        kind: BuiltinKind::Operation(Op::Pow),
+        description: "raise the first number to the power of the second",
+        example: "2 10 ^",
+    },
+    Builtin {
+        name: "neg",
+        kind: BuiltinKind::Function(Func::Neg),
+        description: "change the sign of a number",
+        example: "3 neg",
+    },
+    Builtin {
+        name: "sqrt",
+        kind: BuiltinKind::Function(Func::Sqrt),
+        description: "square root",
+        example: "2 sqrt",
+    },
+    Builtin {
+        name: "sin",
+        kind: BuiltinKind::Function(Func::Sin),
+        description: "sine of an angle in radians",
+        example: "pi 2 / sin",
+    },
+    Builtin {
+        name: "cos",
+        kind: BuiltinKind::Function(Func::Cos),
+        description: "cosine of an angle in radians",
+        example: "pi cos",
+    },
+    Builtin {
+        name: "tan",
+        kind: BuiltinKind::Function(Func::Tan),
+        description: "tangent of an angle in radians",
+        example: "pi 4 / tan",
+    },
+    Builtin {
+        name: "ln",
+        kind: BuiltinKind::Function(Func::Ln),
+        description: "natural logarithm",
+        example: "e ln",
+    },
+    Builtin {
+        name: "exp",
+        kind: BuiltinKind::Function(Func::Exp),
+        description: "e raised to the power of a number",
+        example: "1 exp",
+    },
+    Builtin {
+        name: "solve",
+        kind: BuiltinKind::Function(Func::Solve),
+        description: "where a quotation is zero, from a guess or within [ low high ]",
+        example: "[ x 2 ^ 2 - ] 1 solve",
+    },
+    Builtin {
+        name: "integrate",
+        kind: BuiltinKind::Function(Func::Integrate),
+        description:
+            "integral of a quotation between two numbers, the error estimate goes in `ierr`",
+        example: "[ x 2 ^ ] 0 3 integrate",
+    },
+    Builtin {
+        name: "sum",
+        kind: BuiltinKind::Function(Func::Sum),
+        description: "add up a quotation for each whole number from the first to the second",
+        example: "[ k 2 ^ ] 1 10 sum",
+    },
+    Builtin {
+        name: "product",
+        kind: BuiltinKind::Function(Func::Product),
+        description: "multiply a quotation for each whole number from the first to the second",
+        example: "[ k ] 1 5 product",
+    },
+    Builtin {
+        name: "unix",
+        kind: BuiltinKind::Function(Func::Unix),
+        description: "seconds from 1970-01-01 UTC to a date",
+        example: "2024-04-07 unix",
+    },
+    Builtin {
+        name: "iso",
+        kind: BuiltinKind::Function(Func::Iso),
+        description: "the date a number of seconds after 1970-01-01 UTC",
+        example: "1712448000 iso",
+    },
+    Builtin {
+        name: "now",
+        kind: BuiltinKind::Function(Func::Now),
+        description: "the current date and time in UTC",
+        example: "now 1h +",
+    },
+    Builtin {
+        name: "today",
+        kind: BuiltinKind::Function(Func::Today),
+        description: "the current date in UTC",
+        example: "today 2024-01-01 -",
+    },
+    Builtin {
+        name: "s+",
+        kind: BuiltinKind::Function(Func::Accumulate),
+        description: "add a sample to the statistics register, giving the count",
+        example: "4.5 s+",
+    },
+    Builtin {
+        name: "xy+",
+        kind: BuiltinKind::Function(Func::AccumulatePair),
+        description: "add an x/y pair to the statistics register, giving the count",
+        example: "1 3.2 xy+",
+    },
+    Builtin {
+        name: "sclear",
+        kind: BuiltinKind::Function(Func::ClearStats),
+        description: "empty the statistics register, giving the count",
+        example: "sclear",
+    },
+    Builtin {
+        name: "mean",
+        kind: BuiltinKind::Function(Func::Mean),
+        description: "mean of the samples",
+        example: "mean",
+    },
+    Builtin {
+        name: "median",
+        kind: BuiltinKind::Function(Func::Median),
+        description: "middle of the samples in order",
+        example: "median",
+    },
+    Builtin {
+        name: "var",
+        kind: BuiltinKind::Function(Func::Var),
+        description: "sample variance of the samples",
+        example: "var",
+    },
+    Builtin {
+        name: "sdev",
+        kind: BuiltinKind::Function(Func::Sdev),
+        description: "sample standard deviation of the samples",
+        example: "sdev",
+    },
+    Builtin {
+        name: "smin",
+        kind: BuiltinKind::Function(Func::Min),
+        description: "smallest sample, not `min` which is minutes",
+        example: "smin",
+    },
+    Builtin {
+        name: "max",
+        kind: BuiltinKind::Function(Func::Max),
+        description: "largest sample",
+        example: "max",
+    },
+    Builtin {
+        name: "linreg",
+        kind: BuiltinKind::Function(Func::Linreg),
+        description: "[ slope intercept r ] of a line fitted to the x/y pairs",
+        example: "linreg",
+    },
+    Builtin {
+        name: "predict",
+        kind: BuiltinKind::Function(Func::Predict),
+        description: "y on the line fitted to the x/y pairs at an x",
+        example: "10 predict",
+    },
+    Builtin {
+        name: "!",
+        kind: BuiltinKind::Function(Func::Factorial),
+        description: "factorial of a whole number",
+        example: "5 !",
+    },
+    Builtin {
+        name: "ncr",
+        kind: BuiltinKind::Function(Func::Ncr),
+        description: "ways to choose the second number of things from the first",
+        example: "10 3 ncr",
+    },
+    Builtin {
+        name: "npr",
+        kind: BuiltinKind::Function(Func::Npr),
+        description: "ways to arrange the second number of things from the first",
+        example: "10 3 npr",
+    },
+    Builtin {
+        name: "gcd",
+        kind: BuiltinKind::Function(Func::Gcd),
+        description: "greatest common divisor",
+        example: "12 18 gcd",
+    },
+    Builtin {
+        name: "lcm",
+        kind: BuiltinKind::Function(Func::Lcm),
+        description: "least common multiple",
+        example: "4 6 lcm",
+    },
+    Builtin {
+        name: "isprime",
+        kind: BuiltinKind::Function(Func::IsPrime),
+        description: "1 if a number is prime, 0 if not",
+        example: "97 isprime",
+    },
+    Builtin {
+        name: "factor",
+        kind: BuiltinKind::Function(Func::Factor),
+        description: "list of the prime factors of a number",
+        example: "360 factor",
+    },
+    Builtin {
+        name: "modpow",
+        kind: BuiltinKind::Function(Func::ModPow),
+        description: "the first number to the power of the second, modulo the third",
+        example: "4 13 497 modpow",
+    },
+    Builtin {
+        name: "modinv",
+        kind: BuiltinKind::Function(Func::ModInv),
+        description: "inverse of the first number modulo the second",
+        example: "3 11 modinv",
+    },
+    Builtin {
+        name: "pi",
+        kind: BuiltinKind::Constant(std::f32::consts::PI),
+        description: "ratio of a circle's circumference to its diameter",
+        example: "2 pi *",
+    },
+    Builtin {
+        name: "e",
+        kind: BuiltinKind::Constant(std::f32::consts::E),
+        description: "base of the natural logarithm",
+        example: "e 2 *",
+    },
+    Builtin {
+        name: "lastx",
+        kind: BuiltinKind::LastX,
+        description: "the last value an operator consumed",
+        example: "lastx 2 *",
+    },
+];
+
+pub fn builtin(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
 }
 
 pub fn tokenize(text: String) -> Vec<Token> {
-    let mut tokens = vec![];
-    let mut stream = text.chars().into_iter().peekable();
-
-    let mut buffer = vec![];
-    let mut skip_to_whitespace = false;
-    while let Some(ch) = stream.next() {
-        if skip_to_whitespace {
-            if ch.is_whitespace() {
-                skip_to_whitespace = false;
+    // Each open `[` gets its own list of tokens until the matching `]`.
+    let mut quotes: Vec<Vec<Token>> = vec![vec![]];
+    for lexeme in lexemes(&text) {
+        match lexeme {
+            "[" => quotes.push(vec![]),
+            "]" if quotes.len() > 1 => {
+                let body = quotes.pop().unwrap();
+                quotes.last_mut().unwrap().push(Token::Quote(body));
             }
-            continue;
+            lexeme => quotes.last_mut().unwrap().push(Token::from_word(lexeme)),
         }
-        let next = stream.peek();
-        match ch {
-            ch if ch.is_whitespace() => {
-                buffer.clear();
+    }
+    while quotes.len() > 1 {
+        let body = quotes.pop().unwrap();
+        let tokens = quotes.last_mut().unwrap();
+        tokens.push(Token::Unrecognized(
+            "[".into(),
+            TokenParseError::UnclosedQuote,
+        ));
+        tokens.extend(body);
+    }
+    quotes.pop().unwrap()
+}
+
+/// Splits on whitespace, with separators and brackets always standing on
+/// their own even when written right next to a word.
+fn lexemes(text: &str) -> Vec<&str> {
+    let mut lexemes = vec![];
+    for word in text.split_whitespace() {
+        let mut rest = word;
+        while let Some(at) = rest.find(is_delimiter) {
+            if at > 0 {
+                lexemes.push(&rest[..at]);
             }
-            '+' if buffer.is_empty() && (next.is_none() || next.unwrap().is_whitespace()) => {
-                tokens.push(Token::Operation(Op::Add))
+            lexemes.push(&rest[at..at + 1]);
+            rest = &rest[at + 1..];
+        }
+        if !rest.is_empty() {
+            lexemes.push(rest);
+        }
+    }
+    lexemes
+}
+
+fn is_delimiter(ch: char) -> bool {
+    matches!(ch, ';' | ',' | '[' | ']')
+}
+
+fn is_operator(ch: char) -> bool {
+    matches!(ch, '+' | '-' | '*' | '/' | '^')
+}
+
+/// Names of variables and user defined words.
+pub fn is_identifier(word: &str) -> bool {
+    let mut chars = word.chars();
+    chars
+        .next()
+        .is_some_and(|ch| ch.is_alphabetic() || ch == '_')
+        && chars.all(|ch| ch.is_alphanumeric() || ch == '_')
+        && builtin(word).is_none()
+        && Unit::parse(word).is_none()
+        && Currency::parse(word).is_none()
+        && dates::parse_duration(word).is_none()
+}
+
+impl Token {
+    /// Classifies a single whitespace delimited word, keeping the original
+    /// text around when it is not something we understand.
+    pub fn from_word(word: &str) -> Token {
+        match word {
+            ";" | "," => Token::Separator,
+            "]" => Token::Unrecognized(word.into(), TokenParseError::UnmatchedBracket),
+            word => match builtin(word) {
+                Some(Builtin {
+                    kind: BuiltinKind::Operation(op),
+                    ..
+                }) => Token::Operation(*op),
+                Some(Builtin {
+                    kind: BuiltinKind::Function(func),
+                    ..
+                }) => Token::Function(*func),
+                Some(Builtin {
+                    kind: BuiltinKind::Constant(n),
+                    ..
+                }) => Token::Float(*n),
+                Some(Builtin {
+                    kind: BuiltinKind::LastX,
+                    ..
+                }) => Token::LastX,
+                None => Token::try_from(word)
+                    .unwrap_or_else(|reason| Token::Unrecognized(word.into(), reason)),
+            },
+        }
+    }
+}
+
+impl TokenParseError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            TokenParseError::ParseFloatError => "E0001",
+            TokenParseError::IntParseError => "E0002",
+            TokenParseError::InvalidNumber => "E0003",
+            TokenParseError::UnknownWord => "E0004",
+            TokenParseError::GluedOperator => "E0005",
+            TokenParseError::InvalidName => "E0010",
+            TokenParseError::UnclosedQuote => "E0011",
+            TokenParseError::UnmatchedBracket => "E0012",
+            TokenParseError::InvalidField => "E0017",
+            TokenParseError::InvalidDate => "E0045",
+        }
+    }
+}
+
+impl Display for TokenParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenParseError::ParseFloatError => write!(f, "malformed decimal number"),
+            TokenParseError::IntParseError => write!(f, "integer is out of range"),
+            TokenParseError::InvalidNumber => write!(f, "not a valid number"),
+            TokenParseError::UnknownWord => write!(f, "not a known operator or number"),
+            TokenParseError::GluedOperator => {
+                write!(
+                    f,
+                    "operator attached to a number, separate them with a space"
+                )
             }
-            '-' if buffer.is_empty() && (next.is_none() || next.unwrap().is_whitespace()) => {
-                tokens.push(Token::Operation(Op::Sub))
+            TokenParseError::InvalidName => {
+                write!(
+                    f,
+                    "not a valid name, names start with a letter and can't be builtins, units, currencies or durations"
+                )
             }
-            '*' if buffer.is_empty() && (next.is_none() || next.unwrap().is_whitespace()) => {
-                tokens.push(Token::Operation(Op::Mult))
+            TokenParseError::UnclosedQuote => write!(f, "`[` is never closed by a `]`"),
+            TokenParseError::UnmatchedBracket => write!(f, "`]` has no matching `[`"),
+            TokenParseError::InvalidField => {
+                write!(
+                    f,
+                    "fields are `$` and a column number from 1 or a column name"
+                )
             }
-            '/' if buffer.is_empty() && (next.is_none() || next.unwrap().is_whitespace()) => {
-                tokens.push(Token::Operation(Op::Div))
+            TokenParseError::InvalidDate => {
+                write!(
+                    f,
+                    "not a valid date, the month, day or time of day is out of range"
+                )
             }
-            '-' if buffer.is_empty() => buffer.push(ch),
-            '0'..='9' | '.' => {
-                buffer.push(ch);
-                if next.is_none() || next.unwrap().is_whitespace() {
-                    tokens.push(Token::try_from(&buffer).unwrap_or(Token::Unrecognized));
+        }
+    }
+}
+
+impl std::error::Error for TokenParseError {}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Op::Add => write!(f, "+"),
+            Op::Sub => write!(f, "-"),
+            Op::Mult => write!(f, "*"),
+            Op::Div => write!(f, "/"),
+            Op::Pow => write!(f, "^"),
+        }
+    }
+}
+
+impl Display for Func {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Func::Neg => write!(f, "neg"),
+            Func::Sqrt => write!(f, "sqrt"),
+            Func::Sin => write!(f, "sin"),
+            Func::Cos => write!(f, "cos"),
+            Func::Tan => write!(f, "tan"),
+            Func::Ln => write!(f, "ln"),
+            Func::Exp => write!(f, "exp"),
+            Func::Solve => write!(f, "solve"),
+            Func::Integrate => write!(f, "integrate"),
+            Func::Sum => write!(f, "sum"),
+            Func::Product => write!(f, "product"),
+            Func::Unit(unit) => write!(f, "{}", unit),
+            Func::Convert(unit) => write!(f, ">{}", unit),
+            Func::Currency(currency) => write!(f, "{}", currency.code().to_lowercase()),
+            Func::Exchange(currency) => write!(f, ">{}", currency.code().to_lowercase()),
+            Func::Unix => write!(f, "unix"),
+            Func::Iso => write!(f, "iso"),
+            Func::Now => write!(f, "now"),
+            Func::Today => write!(f, "today"),
+            Func::Accumulate => write!(f, "s+"),
+            Func::AccumulatePair => write!(f, "xy+"),
+            Func::ClearStats => write!(f, "sclear"),
+            Func::Mean => write!(f, "mean"),
+            Func::Median => write!(f, "median"),
+            Func::Var => write!(f, "var"),
+            Func::Sdev => write!(f, "sdev"),
+            Func::Min => write!(f, "smin"),
+            Func::Max => write!(f, "max"),
+            Func::Linreg => write!(f, "linreg"),
+            Func::Predict => write!(f, "predict"),
+            Func::Factorial => write!(f, "!"),
+            Func::Ncr => write!(f, "ncr"),
+            Func::Npr => write!(f, "npr"),
+            Func::Gcd => write!(f, "gcd"),
+            Func::Lcm => write!(f, "lcm"),
+            Func::IsPrime => write!(f, "isprime"),
+            Func::Factor => write!(f, "factor"),
+            Func::ModPow => write!(f, "modpow"),
+            Func::ModInv => write!(f, "modinv"),
+        }
+    }
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Int(n) => write!(f, "{}", n),
+            Token::Float(n) => write!(f, "{:?}", n),
+            Token::Date(seconds) => write!(f, "{}", dates::iso_datetime(*seconds)),
+            Token::Duration(seconds) => write!(f, "{}", dates::duration_string(*seconds)),
+            Token::Operation(op) => write!(f, "{}", op),
+            Token::Function(func) => write!(f, "{}", func),
+            Token::Separator => write!(f, ";"),
+            Token::Symbol(name) => write!(f, "{}", name),
+            Token::Store(name) => write!(f, "={}", name),
+            Token::LastX => write!(f, "lastx"),
+            Token::Field(field) => write!(f, "{}", field),
+            Token::Quote(body) => {
+                write!(f, "[")?;
+                for token in body {
+                    write!(f, " {}", token)?;
                 }
+                write!(f, " ]")
             }
-            _ => {
-                tokens.push(Token::Unrecognized);
-                skip_to_whitespace = true;
-            }
+            Token::Unrecognized(word, _) => write!(f, "{}", word),
+        }
+    }
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Field::Index(n) => write!(f, "${}", n),
+            Field::Name(name) => write!(f, "${}", name),
         }
     }
+}
+
+impl TryFrom<&str> for Field {
+    type Error = TokenParseError;
 
-    tokens
+    /// Parses what comes after the `$`.
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        if value.is_empty() {
+            Err(TokenParseError::InvalidField)
+        } else if value.chars().all(|ch| ch.is_ascii_digit()) {
+            match value.parse() {
+                Ok(0) | Err(_) => Err(TokenParseError::InvalidField),
+                Ok(n) => Ok(Field::Index(n)),
+            }
+        } else {
+            Ok(Field::Name(value.into()))
+        }
+    }
 }
 
 impl From<ParseFloatError> for TokenParseError {
@@ -81,14 +754,48 @@ impl From<ParseIntError> for TokenParseError {
     }
 }
 
-impl TryFrom<&Vec<char>> for Token {
+impl TryFrom<&str> for Token {
     type Error = TokenParseError;
 
-    fn try_from(value: &Vec<char>) -> Result<Self, Self::Error> {
-        if value.contains(&'.') {
-            Ok(Token::Float(String::from_iter(value).parse()?))
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let digits = value.strip_prefix('-').unwrap_or(value);
+        let numeric =
+            !digits.is_empty() && digits.chars().all(|ch| ch.is_ascii_digit() || ch == '.');
+
+        if numeric && digits.contains('.') {
+            Ok(Token::Float(value.parse()?))
+        } else if numeric {
+            Ok(Token::Int(value.parse()?))
+        } else if let Some(seconds) = dates::parse_datetime(value) {
+            Ok(Token::Date(seconds))
+        } else if let Some(seconds) = dates::parse_duration(value) {
+            Ok(Token::Duration(seconds))
+        } else if dates::is_date_shaped(value) {
+            Err(TokenParseError::InvalidDate)
+        } else if let Some(field) = value.strip_prefix('$') {
+            Ok(Token::Field(Field::try_from(field)?))
+        } else if value.contains(is_operator) && value.contains(|ch: char| ch.is_ascii_digit()) {
+            Err(TokenParseError::GluedOperator)
+        } else if digits.starts_with(|ch: char| ch.is_ascii_digit() || ch == '.') {
+            Err(TokenParseError::InvalidNumber)
+        } else if let Some(unit) = Unit::parse(value) {
+            Ok(Token::Function(Func::Unit(unit)))
+        } else if let Some(unit) = value.strip_prefix('>').and_then(Unit::parse) {
+            Ok(Token::Function(Func::Convert(unit)))
+        } else if let Some(currency) = Currency::parse(value) {
+            Ok(Token::Function(Func::Currency(currency)))
+        } else if let Some(currency) = value.strip_prefix('>').and_then(Currency::parse) {
+            Ok(Token::Function(Func::Exchange(currency)))
+        } else if is_identifier(value) {
+            Ok(Token::Symbol(value.into()))
+        } else if let Some(name) = value.strip_prefix('=') {
+            if is_identifier(name) {
+                Ok(Token::Store(name.into()))
+            } else {
+                Err(TokenParseError::InvalidName)
+            }
         } else {
-            Ok(Token::Int(String::from_iter(value).parse()?))
+            Err(TokenParseError::UnknownWord)
         }
     }
 }
@@ -97,39 +804,202 @@ impl TryFrom<&Vec<char>> for Token {
 mod test {
     use super::*;
 
+    #[test]
+    fn test_display() {
+        assert_eq!(Token::Int(-2).to_string(), "-2");
+        assert_eq!(Token::Float(10.0).to_string(), "10.0");
+        assert_eq!(Token::Operation(Op::Div).to_string(), "/");
+        assert_eq!(
+            Token::Unrecognized("3-2".into(), TokenParseError::GluedOperator).to_string(),
+            "3-2"
+        );
+        assert_eq!(
+            tokenize("[ 2 * ] =double".into())
+                .iter()
+                .map(Token::to_string)
+                .collect::<Vec<_>>(),
+            vec!["[ 2 * ]", "=double"]
+        );
+        assert_eq!(TokenParseError::InvalidNumber.code(), "E0003");
+    }
+
+    #[test]
+    fn test_tokenize_names() {
+        assert_eq!(
+            tokenize("pi e".into()),
+            vec![
+                Token::Float(std::f32::consts::PI),
+                Token::Float(std::f32::consts::E)
+            ]
+        );
+        assert_eq!(tokenize("lastx".into()), vec![Token::LastX]);
+        assert_eq!(
+            tokenize("2 10 ^ sqrt neg".into()),
+            vec![
+                Token::Int(2),
+                Token::Int(10),
+                Token::Operation(Op::Pow),
+                Token::Function(Func::Sqrt),
+                Token::Function(Func::Neg)
+            ]
+        );
+        assert_eq!(
+            tokenize("3 4 + =x x_2".into()),
+            vec![
+                Token::Int(3),
+                Token::Int(4),
+                Token::Operation(Op::Add),
+                Token::Store("x".into()),
+                Token::Symbol("x_2".into())
+            ]
+        );
+        for invalid in ["=", "=2x", "=pi", "=+", "=lastx", "=h", "=s", "=usd"] {
+            assert_eq!(
+                tokenize(invalid.into()),
+                vec![Token::Unrecognized(
+                    invalid.into(),
+                    TokenParseError::InvalidName
+                )]
+            );
+        }
+
+        assert_eq!(
+            tokenize("[2 *] =double".into()),
+            vec![
+                Token::Quote(vec![Token::Int(2), Token::Operation(Op::Mult)]),
+                Token::Store("double".into())
+            ]
+        );
+        assert_eq!(
+            tokenize("[ [ 1 ] ]".into()),
+            vec![Token::Quote(vec![Token::Quote(vec![Token::Int(1)])])]
+        );
+        assert_eq!(
+            tokenize("1 [ 2 *".into()),
+            vec![
+                Token::Int(1),
+                Token::Unrecognized("[".into(), TokenParseError::UnclosedQuote),
+                Token::Int(2),
+                Token::Operation(Op::Mult)
+            ]
+        );
+        assert_eq!(
+            tokenize("$2 $unit-price *".into()),
+            vec![
+                Token::Field(Field::Index(2)),
+                Token::Field(Field::Name("unit-price".into())),
+                Token::Operation(Op::Mult)
+            ]
+        );
+        for invalid in ["$", "$0", "$99999999999999999999"] {
+            assert_eq!(
+                tokenize(invalid.into()),
+                vec![Token::Unrecognized(
+                    invalid.into(),
+                    TokenParseError::InvalidField
+                )]
+            );
+        }
+        assert_eq!(Token::Field(Field::Index(3)).to_string(), "$3");
+        assert_eq!(
+            tokenize("1 ]".into()),
+            vec![
+                Token::Int(1),
+                Token::Unrecognized("]".into(), TokenParseError::UnmatchedBracket)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_dates() {
+        let day = 19820 * dates::SECONDS_PER_DAY;
+        assert_eq!(
+            tokenize("2024-04-07 30d + 2024-04-07T09:30Z -1h30m P1DT12H".into()),
+            vec![
+                Token::Date(day),
+                Token::Duration(30 * dates::SECONDS_PER_DAY),
+                Token::Operation(Op::Add),
+                Token::Date(day + 34200),
+                Token::Duration(-5400),
+                Token::Duration(129600)
+            ]
+        );
+        assert_eq!(Token::Date(day + 34200).to_string(), "2024-04-07T09:30:00");
+        assert_eq!(Token::Duration(129600).to_string(), "1d12h");
+        for invalid in ["2024-02-30", "2023-02-29", "2024-13-01", "2024-04-07T25:00"] {
+            assert_eq!(
+                tokenize(invalid.into()),
+                vec![Token::Unrecognized(
+                    invalid.into(),
+                    TokenParseError::InvalidDate
+                )],
+                "{}",
+                invalid
+            );
+        }
+        assert!(matches!(
+            tokenize("30x".into())[..],
+            [Token::Unrecognized(_, _)]
+        ));
+        assert_eq!(
+            tokenize("=P1D".into()),
+            vec![Token::Unrecognized(
+                "=P1D".into(),
+                TokenParseError::InvalidName
+            )]
+        );
+    }
+
     #[test]
     fn test_tokenize() {
         assert_eq!(tokenize("2".into()), vec![Token::Int(2)]);
         assert_eq!(tokenize("22".into()), vec![Token::Int(22)]);
         assert_eq!(tokenize("-2".into()), vec![Token::Int(-2)]);
 
-        assert_eq!(tokenize("f".into()), vec![Token::Unrecognized]);
-        assert_eq!(tokenize("3-".into()), vec![Token::Unrecognized]);
-        assert_eq!(tokenize("3+".into()), vec![Token::Unrecognized]);
-        assert_eq!(tokenize("3*".into()), vec![Token::Unrecognized]);
-        assert_eq!(tokenize("3/".into()), vec![Token::Unrecognized]);
-        assert_eq!(tokenize("+3".into()), vec![Token::Unrecognized]);
-        assert_eq!(tokenize("*3".into()), vec![Token::Unrecognized]);
-        assert_eq!(tokenize("/3".into()), vec![Token::Unrecognized]);
-        assert_eq!(tokenize("-3+".into()), vec![Token::Unrecognized]);
-        assert_eq!(tokenize("+3-".into()), vec![Token::Unrecognized]);
-        assert_eq!(tokenize("*3/".into()), vec![Token::Unrecognized]);
-        assert_eq!(tokenize("/3*".into()), vec![Token::Unrecognized]);
-        assert_eq!(tokenize("3-2".into()), vec![Token::Unrecognized]);
-        assert_eq!(tokenize("3-2.3".into()), vec![Token::Unrecognized]);
-        assert_eq!(tokenize("3.0-2".into()), vec![Token::Unrecognized]);
+        assert_eq!(tokenize("f".into()), vec![Token::Symbol("f".into())]);
+        for glued in [
+            "3-", "3+", "3*", "3/", "+3", "*3", "/3", "-3+", "+3-", "*3/", "/3*", "3-2", "3-2.3",
+            "3.0-2", "2^3",
+        ] {
+            assert_eq!(
+                tokenize(glued.into()),
+                vec![Token::Unrecognized(
+                    glued.into(),
+                    TokenParseError::GluedOperator
+                )]
+            );
+        }
         assert_eq!(
             tokenize("22 asdf *(".into()),
-            vec![Token::Int(22), Token::Unrecognized, Token::Unrecognized]
+            vec![
+                Token::Int(22),
+                Token::Symbol("asdf".into()),
+                Token::Unrecognized("*(".into(), TokenParseError::UnknownWord)
+            ]
         );
         assert_eq!(
             tokenize("2f f32 3f65".into()),
             vec![
-                Token::Unrecognized,
-                Token::Unrecognized,
-                Token::Unrecognized
+                Token::Unrecognized("2f".into(), TokenParseError::InvalidNumber),
+                Token::Symbol("f32".into()),
+                Token::Unrecognized("3f65".into(), TokenParseError::InvalidNumber)
             ]
         );
+        assert_eq!(
+            tokenize("1.2.3 - .".into()),
+            vec![
+                Token::Unrecognized("1.2.3".into(), TokenParseError::ParseFloatError),
+                Token::Operation(Op::Sub),
+                Token::Unrecognized(".".into(), TokenParseError::ParseFloatError)
+            ]
+        );
+        assert_eq!(
+            tokenize("1000000000000000000000000000000000000000".into()),
+            vec![Token::Unrecognized(
+                "1000000000000000000000000000000000000000".into(),
+                TokenParseError::IntParseError
+            )]
+        );
 
         assert_eq!(tokenize("1.4".into()), vec![Token::Float(1.4)]);
         assert_eq!(tokenize("10.".into()), vec![Token::Float(10.0)]);
@@ -164,6 +1034,35 @@ mod test {
             vec![Token::Int(10), Token::Int(4), Token::Operation(Op::Mult)]
         );
 
+        assert_eq!(
+            tokenize("1 2 + ; 3 4 *".into()),
+            vec![
+                Token::Int(1),
+                Token::Int(2),
+                Token::Operation(Op::Add),
+                Token::Separator,
+                Token::Int(3),
+                Token::Int(4),
+                Token::Operation(Op::Mult)
+            ]
+        );
+        assert_eq!(
+            tokenize("1 2 +, 3;4".into()),
+            vec![
+                Token::Int(1),
+                Token::Int(2),
+                Token::Operation(Op::Add),
+                Token::Separator,
+                Token::Int(3),
+                Token::Separator,
+                Token::Int(4)
+            ]
+        );
+        assert_eq!(
+            tokenize("1 2 +, 3;4".into()),
+            tokenize("1 2 + , 3 ; 4".into())
+        );
+
         assert_eq!(
             tokenize("10 4 * 2 +".into()),
             vec![
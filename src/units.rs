@@ -0,0 +1,421 @@
+use std::fmt::Display;
+
+use crate::interpreter::EvalError;
+use crate::tokenizer::{Func, Op};
+
+/// Powers of the SI base dimensions, in the order of `BASE`.
+pub type Dimension = [i8; 7];
+
+pub const DIMENSIONLESS: Dimension = [0; 7];
+
+/// The SI base unit of each dimension.
+const BASE: [&str; 7] = ["m", "kg", "s", "A", "K", "mol", "cd"];
+
+const LENGTH: Dimension = [1, 0, 0, 0, 0, 0, 0];
+const MASS: Dimension = [0, 1, 0, 0, 0, 0, 0];
+const TIME: Dimension = [0, 0, 1, 0, 0, 0, 0];
+const CURRENT: Dimension = [0, 0, 0, 1, 0, 0, 0];
+const TEMPERATURE: Dimension = [0, 0, 0, 0, 1, 0, 0];
+const AMOUNT: Dimension = [0, 0, 0, 0, 0, 1, 0];
+const LUMINOSITY: Dimension = [0, 0, 0, 0, 0, 0, 1];
+const AREA: Dimension = [2, 0, 0, 0, 0, 0, 0];
+const VOLUME: Dimension = [3, 0, 0, 0, 0, 0, 0];
+const SPEED: Dimension = [1, 0, -1, 0, 0, 0, 0];
+const FREQUENCY: Dimension = [0, 0, -1, 0, 0, 0, 0];
+const FORCE: Dimension = [1, 1, -2, 0, 0, 0, 0];
+const PRESSURE: Dimension = [-1, 1, -2, 0, 0, 0, 0];
+const ENERGY: Dimension = [2, 1, -2, 0, 0, 0, 0];
+const POWER: Dimension = [2, 1, -3, 0, 0, 0, 0];
+const CHARGE: Dimension = [0, 0, 1, 1, 0, 0, 0];
+const VOLTAGE: Dimension = [2, 1, -3, -1, 0, 0, 0];
+const RESISTANCE: Dimension = [2, 1, -3, -2, 0, 0, 0];
+
+struct Definition {
+    name: &'static str,
+    /// How many SI base units one of these is.
+    scale: f64,
+    dimension: Dimension,
+    /// Whether it can be written with an SI prefix, like `km`.
+    prefixed: bool,
+}
+
+const fn unit(name: &'static str, scale: f64, dimension: Dimension, prefixed: bool) -> Definition {
+    Definition {
+        name,
+        scale,
+        dimension,
+        prefixed,
+    }
+}
+
+const UNITS: &[Definition] = &[
+    unit("m", 1.0, LENGTH, true),
+    unit("g", 1e-3, MASS, true),
+    unit("s", 1.0, TIME, true),
+    unit("A", 1.0, CURRENT, true),
+    unit("K", 1.0, TEMPERATURE, true),
+    unit("mol", 1.0, AMOUNT, true),
+    unit("cd", 1.0, LUMINOSITY, true),
+    unit("L", 1e-3, VOLUME, true),
+    unit("Hz", 1.0, FREQUENCY, true),
+    unit("N", 1.0, FORCE, true),
+    unit("Pa", 1.0, PRESSURE, true),
+    unit("J", 1.0, ENERGY, true),
+    unit("W", 1.0, POWER, true),
+    unit("C", 1.0, CHARGE, true),
+    unit("V", 1.0, VOLTAGE, true),
+    unit("ohm", 1.0, RESISTANCE, true),
+    unit("cal", 4.184, ENERGY, true),
+    unit("min", 60.0, TIME, false),
+    unit("h", 3600.0, TIME, false),
+    unit("day", 86400.0, TIME, false),
+    unit("ha", 1e4, AREA, false),
+    unit("in", 0.0254, LENGTH, false),
+    unit("ft", 0.3048, LENGTH, false),
+    unit("yd", 0.9144, LENGTH, false),
+    unit("mi", 1609.344, LENGTH, false),
+    unit("acre", 4046.8564224, AREA, false),
+    unit("gal", 3.785411784e-3, VOLUME, false),
+    unit("oz", 28.349523125e-3, MASS, false),
+    unit("lb", 0.45359237, MASS, false),
+    unit("mph", 0.44704, SPEED, false),
+    unit("lbf", 4.4482216152605, FORCE, false),
+    unit("psi", 6894.757293168361, PRESSURE, false),
+    unit("hp", 745.6998715822702, POWER, false),
+];
+
+const PREFIXES: &[(&str, f64)] = &[
+    ("p", 1e-12),
+    ("n", 1e-9),
+    ("u", 1e-6),
+    ("µ", 1e-6),
+    ("m", 1e-3),
+    ("c", 1e-2),
+    ("k", 1e3),
+    ("M", 1e6),
+    ("G", 1e9),
+    ("T", 1e12),
+];
+
+/// A unit from the table, possibly with a prefix. Written as its name when
+/// serialized so the table can change.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "String", try_from = "String")
+)]
+pub struct Unit {
+    prefix: Option<usize>,
+    base: usize,
+}
+
+impl Unit {
+    /// Looks up a unit by name. Whole names win over prefixed ones, so `min`
+    /// is minutes rather than milli-inches.
+    pub fn parse(name: &str) -> Option<Unit> {
+        if let Some(base) = UNITS.iter().position(|unit| unit.name == name) {
+            return Some(Unit { prefix: None, base });
+        }
+        PREFIXES
+            .iter()
+            .enumerate()
+            .find_map(|(prefix, (symbol, _))| {
+                let rest = name.strip_prefix(symbol)?;
+                let base = UNITS
+                    .iter()
+                    .position(|unit| unit.prefixed && unit.name == rest)?;
+                Some(Unit {
+                    prefix: Some(prefix),
+                    base,
+                })
+            })
+    }
+
+    pub fn scale(&self) -> f64 {
+        let prefix = self.prefix.map_or(1.0, |prefix| PREFIXES[prefix].1);
+        prefix * UNITS[self.base].scale
+    }
+
+    pub fn dimension(&self) -> Dimension {
+        UNITS[self.base].dimension
+    }
+}
+
+impl Display for Unit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(prefix) = self.prefix {
+            write!(f, "{}", PREFIXES[prefix].0)?;
+        }
+        write!(f, "{}", UNITS[self.base].name)
+    }
+}
+
+impl From<Unit> for String {
+    fn from(value: Unit) -> Self {
+        value.to_string()
+    }
+}
+
+impl TryFrom<String> for Unit {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Unit::parse(&value).ok_or_else(|| format!("unknown unit `{}`", value))
+    }
+}
+
+/// Writes a dimension in SI base units, like `m*kg/s^2`.
+pub fn dimension_name(dimension: &Dimension) -> String {
+    let powers = |positive: bool| {
+        BASE.iter()
+            .zip(dimension)
+            .filter(|(_, power)| **power != 0 && (**power > 0) == positive)
+            .map(|(name, power)| match power.abs() {
+                1 => name.to_string(),
+                n => format!("{}^{}", name, n),
+            })
+            .collect::<Vec<_>>()
+    };
+    let (over, under) = (powers(true), powers(false));
+    let over = match over.is_empty() {
+        true => "1".to_string(),
+        false => over.join("*"),
+    };
+    match under.len() {
+        0 => over,
+        1 => format!("{}/{}", over, under[0]),
+        _ => format!("{}/({})", over, under.join("*")),
+    }
+}
+
+/// A number with units. The value is kept in SI base units whatever unit it
+/// was written in, which is remembered for display when there is one.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quantity {
+    pub value: f32,
+    pub dimension: Dimension,
+    pub unit: Option<Unit>,
+}
+
+impl Quantity {
+    pub fn new(value: f32, unit: Unit) -> Quantity {
+        Quantity {
+            value: (value as f64 * unit.scale()) as f32,
+            dimension: unit.dimension(),
+            unit: Some(unit),
+        }
+    }
+
+    /// A number without units, to do arithmetic with quantities.
+    pub fn plain(value: f32) -> Quantity {
+        Quantity {
+            value,
+            dimension: DIMENSIONLESS,
+            unit: None,
+        }
+    }
+
+    fn is_plain(&self) -> bool {
+        self.dimension == DIMENSIONLESS
+    }
+
+    /// The value in the unit it is shown in.
+    pub fn shown(&self) -> f32 {
+        match self.unit {
+            Some(unit) => (self.value as f64 / unit.scale()) as f32,
+            None => self.value,
+        }
+    }
+
+    pub fn unit_name(&self) -> String {
+        match self.unit {
+            Some(unit) => unit.to_string(),
+            None => dimension_name(&self.dimension),
+        }
+    }
+
+    /// Adding or subtracting keeps the unit of the left hand side.
+    fn same_dimension(&self, other: &Quantity) -> Result<Option<Unit>, EvalError> {
+        match self.dimension == other.dimension {
+            true => Ok(self.unit.or(other.unit)),
+            false => Err(EvalError::DimensionMismatch(
+                self.dimension,
+                other.dimension,
+            )),
+        }
+    }
+
+    pub fn add(&self, other: &Quantity) -> Result<Quantity, EvalError> {
+        Ok(Quantity {
+            value: self.value + other.value,
+            dimension: self.dimension,
+            unit: self.same_dimension(other)?,
+        })
+    }
+
+    pub fn sub(&self, other: &Quantity) -> Result<Quantity, EvalError> {
+        Ok(Quantity {
+            value: self.value - other.value,
+            dimension: self.dimension,
+            unit: self.same_dimension(other)?,
+        })
+    }
+
+    /// Scaling by a plain number keeps the unit, anything else is shown in
+    /// SI base units.
+    fn scaled_unit(&self, other: &Quantity) -> Option<Unit> {
+        match (self.is_plain(), other.is_plain()) {
+            (true, _) => other.unit,
+            (_, true) => self.unit,
+            _ => None,
+        }
+    }
+
+    pub fn mult(&self, other: &Quantity) -> Result<Quantity, EvalError> {
+        Ok(Quantity {
+            value: self.value * other.value,
+            dimension: combine(Op::Mult, self, other, i8::checked_add)?,
+            unit: self.scaled_unit(other),
+        })
+    }
+
+    pub fn div(&self, other: &Quantity) -> Result<Quantity, EvalError> {
+        Ok(Quantity {
+            value: self.value / other.value,
+            dimension: combine(Op::Div, self, other, i8::checked_sub)?,
+            unit: match other.is_plain() {
+                true => self.unit,
+                false => None,
+            },
+        })
+    }
+
+    pub fn pow(&self, exponent: i128) -> Result<Quantity, EvalError> {
+        let out_of_range = || EvalError::PowerOutOfRange(Op::Pow.to_string());
+        let power = i8::try_from(exponent).map_err(|_| out_of_range())?;
+        let mut dimension = DIMENSIONLESS;
+        for (to, from) in dimension.iter_mut().zip(self.dimension) {
+            *to = from.checked_mul(power).ok_or_else(out_of_range)?;
+        }
+        Ok(Quantity {
+            value: self.value.powi(power.into()),
+            dimension,
+            unit: None,
+        })
+    }
+
+    /// Only when every power is even, `m^2` has a square root but `m` doesn't.
+    pub fn sqrt(&self) -> Result<Quantity, EvalError> {
+        if self.dimension.iter().any(|power| power % 2 != 0) {
+            return Err(EvalError::UnitsNotAllowed(
+                Func::Sqrt.to_string(),
+                self.dimension,
+            ));
+        }
+        Ok(Quantity {
+            value: self.value.sqrt(),
+            dimension: self.dimension.map(|power| power / 2),
+            unit: None,
+        })
+    }
+
+    pub fn convert(&self, unit: Unit) -> Result<Quantity, EvalError> {
+        match self.dimension == unit.dimension() {
+            true => Ok(Quantity {
+                unit: Some(unit),
+                ..self.clone()
+            }),
+            false => Err(EvalError::DimensionMismatch(
+                self.dimension,
+                unit.dimension(),
+            )),
+        }
+    }
+}
+
+fn combine(
+    op: Op,
+    x: &Quantity,
+    y: &Quantity,
+    f: fn(i8, i8) -> Option<i8>,
+) -> Result<Dimension, EvalError> {
+    let mut dimension = DIMENSIONLESS;
+    for (i, power) in dimension.iter_mut().enumerate() {
+        *power = f(x.dimension[i], y.dimension[i])
+            .ok_or_else(|| EvalError::PowerOutOfRange(op.to_string()))?;
+    }
+    Ok(dimension)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let name = |text| Unit::parse(text).map(|unit| unit.to_string());
+        assert_eq!(name("m"), Some("m".into()));
+        assert_eq!(name("km"), Some("km".into()));
+        assert_eq!(name("µs"), Some("µs".into()));
+        assert_eq!(name("min"), Some("min".into()));
+        assert_eq!(name("mi"), Some("mi".into()));
+        assert_eq!(name("kft"), None);
+        assert_eq!(name("x"), None);
+        assert_eq!(Unit::parse("kg").unwrap().scale(), 1.0);
+        assert_eq!(Unit::parse("mm").unwrap().scale(), 1e-3);
+        assert_eq!(Unit::parse("N").unwrap().dimension(), FORCE);
+    }
+
+    #[test]
+    fn test_dimension_name() {
+        assert_eq!(dimension_name(&LENGTH), "m");
+        assert_eq!(dimension_name(&SPEED), "m/s");
+        assert_eq!(dimension_name(&FORCE), "m*kg/s^2");
+        assert_eq!(dimension_name(&FREQUENCY), "1/s");
+        assert_eq!(dimension_name(&VOLTAGE), "m^2*kg/(s^3*A)");
+    }
+
+    #[test]
+    fn test_quantity() {
+        let unit = |name| Unit::parse(name).unwrap();
+        let foot = Quantity::new(1.0, unit("ft"));
+        assert_eq!(foot.value, 0.3048);
+        assert_eq!(foot.shown(), 1.0);
+        assert_eq!(foot.convert(unit("m")).unwrap().shown(), 0.3048);
+        assert_eq!(foot.convert(unit("in")).unwrap().shown(), 12.0);
+
+        let yard = foot.mult(&Quantity::plain(3.0)).unwrap();
+        assert_eq!((yard.shown(), yard.unit_name()), (3.0, "ft".into()));
+        let area = foot.mult(&foot).unwrap();
+        assert_eq!(area.unit_name(), "m^2");
+        assert_eq!(
+            area.sqrt(),
+            Ok(Quantity {
+                unit: None,
+                ..foot.clone()
+            })
+        );
+
+        let second = Quantity::new(1.0, unit("s"));
+        assert_eq!(
+            foot.add(&second),
+            Err(EvalError::DimensionMismatch(LENGTH, TIME))
+        );
+        assert_eq!(
+            foot.sqrt(),
+            Err(EvalError::UnitsNotAllowed("sqrt".into(), LENGTH))
+        );
+        assert_eq!(
+            second.convert(unit("m")),
+            Err(EvalError::DimensionMismatch(TIME, LENGTH))
+        );
+        assert_eq!(foot.pow(200), Err(EvalError::PowerOutOfRange("^".into())));
+        assert_eq!(foot.pow(-200), Err(EvalError::PowerOutOfRange("^".into())));
+        assert_eq!(
+            foot.pow(100).and_then(|x| x.mult(&foot.pow(100)?)),
+            Err(EvalError::PowerOutOfRange("*".into()))
+        );
+    }
+}